@@ -0,0 +1,31 @@
+//! Time per eviction for a cache with many count chains, most of them empty.
+//!
+//! Run with `cargo bench --bench eviction`.
+
+use fbr_cache::FbrCache;
+use std::time::Instant;
+
+const ROUNDS: u32 = 1_000_000;
+
+fn main() {
+    // all items share the highest per-count chain, the 63 chains below are empty
+    let mut cache = FbrCache::<u32, u32, 64>::with_age_threshold(1000, 1000);
+    for k in 0..1000 {
+        cache.put_prio_n(k, k, 63);
+    }
+
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        // take the next victim and put it back, which keeps the cache in steady state
+        for (key, value, count) in cache.demote(1) {
+            cache.put_prio_n(key, value, count);
+        }
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "{} evictions in {:?}, {:.1} ns per eviction",
+        ROUNDS,
+        elapsed,
+        elapsed.as_nanos() as f64 / ROUNDS as f64
+    );
+}
@@ -0,0 +1,41 @@
+//! Time per lookup via key versus via handle, for keys that are expensive to hash.
+//!
+//! Run with `cargo bench --bench handle`.
+
+use fbr_cache::FbrCache;
+use std::{hint::black_box, time::Instant};
+
+const ROUNDS: usize = 1_000_000;
+
+fn main() {
+    let keys = (0..100)
+        .map(|i| format!("some/rather/long/path/to/item/{}", i))
+        .collect::<Vec<_>>();
+    let mut cache = FbrCache::<String, usize, 8>::with_age_threshold(100, 100);
+    for (i, key) in keys.iter().enumerate() {
+        cache.put(key.clone(), i);
+    }
+    let handles = keys
+        .iter()
+        .map(|key| cache.handle(key).unwrap())
+        .collect::<Vec<_>>();
+
+    let start = Instant::now();
+    for n in 0..ROUNDS {
+        black_box(cache.get(&keys[n % keys.len()]));
+    }
+    let by_key = start.elapsed();
+
+    let start = Instant::now();
+    for n in 0..ROUNDS {
+        // all items stay in the cache, so the handles remain valid
+        black_box(unsafe { cache.get_unchecked(&handles[n % handles.len()]) });
+    }
+    let by_handle = start.elapsed();
+
+    println!(
+        "get: {:.1} ns, get_unchecked: {:.1} ns per lookup",
+        by_key.as_nanos() as f64 / ROUNDS as f64,
+        by_handle.as_nanos() as f64 / ROUNDS as f64
+    );
+}
@@ -0,0 +1,244 @@
+use crate::{FbrCache, FbrEntry};
+use intrusive_collections::UnsafeRef;
+use std::{borrow::Borrow, hash::Hash, ops::Deref};
+
+/// A view into a single item of an [`FbrCache`], obtained from [`FbrCache::entry`]
+pub enum Entry<'a, K, V, const C_MAX: usize> {
+    Occupied(OccupiedEntry<'a, K, V, C_MAX>),
+    Vacant(VacantEntry<'a, K, V, C_MAX>),
+}
+
+/// An item that is present in the cache
+pub struct OccupiedEntry<'a, K, V, const C_MAX: usize> {
+    pub(crate) cache: &'a mut FbrCache<K, V, C_MAX>,
+    pub(crate) entry: UnsafeRef<FbrEntry<K, V>>,
+}
+
+/// A view into a single item of an [`FbrCache`], obtained from [`FbrCache::entry_ref`]
+pub enum EntryRef<'a, 'q, K, Q: ?Sized, V, const C_MAX: usize> {
+    Occupied(OccupiedEntry<'a, K, V, C_MAX>),
+    Vacant(VacantEntryRef<'a, 'q, K, Q, V, C_MAX>),
+}
+
+/// A borrowed key that is not present in the cache
+pub struct VacantEntryRef<'a, 'q, K, Q: ?Sized, V, const C_MAX: usize> {
+    pub(crate) cache: &'a mut FbrCache<K, V, C_MAX>,
+    pub(crate) key: &'q Q,
+}
+
+/// A key that is not present in the cache
+pub struct VacantEntry<'a, K, V, const C_MAX: usize> {
+    pub(crate) cache: &'a mut FbrCache<K, V, C_MAX>,
+    pub(crate) key: K,
+}
+
+/// Copy-on-write access to a cached value, obtained from [`FbrCache::get_cow`]
+///
+/// Reading through `Deref` borrows the cached value. The first call to [`Self::to_mut`]
+/// clones it, and the modified clone replaces the cached value when this handle is
+/// dropped; until then the cache still holds the original value.
+pub struct CowValue<'a, V: Clone> {
+    pub(crate) cached: &'a mut V,
+    pub(crate) modified: Option<V>,
+}
+
+impl<V: Clone> CowValue<'_, V> {
+    /// A mutable reference to the modified value, cloning the cached one on first use.
+    pub fn to_mut(&mut self) -> &mut V {
+        let cached = &*self.cached;
+        self.modified.get_or_insert_with(|| cached.clone())
+    }
+
+    /// Returns `true` if the value has been cloned for modification.
+    pub fn is_modified(&self) -> bool {
+        self.modified.is_some()
+    }
+}
+
+impl<V: Clone> Deref for CowValue<'_, V> {
+    type Target = V;
+
+    fn deref(&self) -> &V {
+        self.modified.as_ref().unwrap_or(self.cached)
+    }
+}
+
+impl<V: Clone> Drop for CowValue<'_, V> {
+    fn drop(&mut self) {
+        if let Some(value) = self.modified.take() {
+            *self.cached = value;
+        }
+    }
+}
+
+impl<'a, K: Hash + Eq + Clone, V, const C: usize> Entry<'a, K, V, C> {
+    /// The key of this entry.
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(e) => e.key(),
+            Entry::Vacant(e) => e.key(),
+        }
+    }
+
+    /// Run `f` on the value if the entry is occupied, passing a vacant entry through.
+    ///
+    /// The usage bookkeeping already happened when obtaining the entry.
+    pub fn and_modify<F: FnOnce(&mut V)>(mut self, f: F) -> Self {
+        if let Entry::Occupied(e) = &mut self {
+            f(e.get_mut());
+        }
+        self
+    }
+
+    /// Return the value, putting the given one into the cache if vacant.
+    pub fn or_insert(self, value: V) -> &'a mut V {
+        self.or_insert_with(|| value)
+    }
+
+    /// Return the value, putting the result of `f` into the cache if vacant.
+    pub fn or_insert_with<F: FnOnce() -> V>(self, f: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(e) => e.into_mut(),
+            Entry::Vacant(e) => e.insert(f()),
+        }
+    }
+
+    /// Like [`Self::or_insert`], but with elevated priority as in [`FbrCache::put_prio`].
+    ///
+    /// The priority only applies when inserting, an occupied entry is returned unchanged.
+    pub fn or_insert_prio(self, value: V) -> &'a mut V {
+        self.or_insert_with_prio(|| value)
+    }
+
+    /// Like [`Self::or_insert_with`], but with elevated priority as in [`FbrCache::put_prio`].
+    ///
+    /// The priority only applies when inserting, an occupied entry is returned unchanged.
+    pub fn or_insert_with_prio<F: FnOnce() -> V>(self, f: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(e) => e.into_mut(),
+            Entry::Vacant(e) => e.insert_prio(f()),
+        }
+    }
+}
+
+impl<'a, K, V, const C: usize> OccupiedEntry<'a, K, V, C> {
+    /// The key of this entry.
+    pub fn key(&self) -> &K {
+        &self.entry.key
+    }
+
+    /// The current usage count of this entry.
+    pub fn count(&self) -> usize {
+        self.entry.count
+    }
+
+    /// The value of this entry.
+    pub fn get(&self) -> &V {
+        &self.entry.value
+    }
+
+    /// The value of this entry for modification.
+    pub fn get_mut(&mut self) -> &mut V {
+        unsafe { FbrEntry::value_mut(&self.entry) }
+    }
+
+    /// The value of this entry, bound to the lifetime of the cache borrow.
+    pub fn into_mut(self) -> &'a mut V {
+        unsafe { FbrEntry::value_mut(&self.entry) }
+    }
+}
+
+impl<'a, K: Hash + Eq + Clone, V, const C: usize> OccupiedEntry<'a, K, V, C> {
+    /// Remove this entry from the cache, returning its value.
+    pub fn remove(self) -> V {
+        self.cache.remove_entry(self.entry)
+    }
+}
+
+impl<'a, K: Hash + Eq + Clone, V, const C: usize> VacantEntry<'a, K, V, C> {
+    /// The key of this entry.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Put the given value into the cache, evicting another item if necessary.
+    ///
+    /// Since a reference to the value is returned, the key is admitted regardless of
+    /// [`FbrCache::set_admission`].
+    pub fn insert(self, value: V) -> &'a mut V {
+        let cde = self.cache.insert(self.key, value, 0);
+        unsafe { FbrEntry::value_mut(&cde) }
+    }
+
+    /// Like [`Self::insert`], but with elevated priority as in [`FbrCache::put_prio`].
+    pub fn insert_prio(self, value: V) -> &'a mut V {
+        let cde = self.cache.insert(self.key, value, 1);
+        unsafe { FbrEntry::value_mut(&cde) }
+    }
+
+    /// Like [`Self::insert`], but also returning the item evicted to make room, if any.
+    ///
+    /// With a hard capacity above the capacity (see [`FbrCache::set_hard_capacity`]) more
+    /// items may be evicted in one batch, of which only the last one is returned.
+    pub fn or_insert_evicting(self, value: V) -> (&'a mut V, Option<(K, V)>) {
+        let (cde, evicted) = self.cache.insert_evicting(self.key, value, 0);
+        (unsafe { FbrEntry::value_mut(&cde) }, evicted)
+    }
+}
+
+impl<'a, 'q, K, Q, V, const C: usize> EntryRef<'a, 'q, K, Q, V, C>
+where
+    K: Hash + Eq + Clone + Borrow<Q> + From<&'q Q>,
+    Q: ?Sized,
+{
+    /// Return the value, putting the given one into the cache if vacant.
+    pub fn or_insert(self, value: V) -> &'a mut V {
+        self.or_insert_with(|| value)
+    }
+
+    /// Return the value, putting the result of `f` into the cache if vacant.
+    pub fn or_insert_with<F: FnOnce() -> V>(self, f: F) -> &'a mut V {
+        match self {
+            EntryRef::Occupied(e) => e.into_mut(),
+            EntryRef::Vacant(e) => e.insert(f()),
+        }
+    }
+}
+
+impl<'a, 'q, K: Hash + Eq + Clone, Q: ?Sized, V, const C: usize>
+    VacantEntryRef<'a, 'q, K, Q, V, C>
+{
+    /// The borrowed key of this entry.
+    pub fn key(&self) -> &'q Q {
+        self.key
+    }
+
+    /// Put the given value into the cache, converting the key into its owned form.
+    pub fn insert(self, value: V) -> &'a mut V
+    where
+        K: From<&'q Q>,
+    {
+        let cde = self.cache.insert(K::from(self.key), value, 0);
+        unsafe { FbrEntry::value_mut(&cde) }
+    }
+
+    /// Put the given value into the cache, using `make_key` to create the owned key.
+    ///
+    /// # Panics
+    ///
+    /// If the created key is not equal to the borrowed one, since it might otherwise
+    /// collide with a key already in the cache.
+    pub fn insert_with_key<F: FnOnce(&'q Q) -> K>(self, make_key: F, value: V) -> &'a mut V
+    where
+        K: Borrow<Q>,
+        Q: Eq,
+    {
+        let key = make_key(self.key);
+        assert!(
+            key.borrow() == self.key,
+            "key created by insert_with_key differs from the entry key"
+        );
+        let cde = self.cache.insert(key, value, 0);
+        unsafe { FbrEntry::value_mut(&cde) }
+    }
+}
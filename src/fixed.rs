@@ -0,0 +1,393 @@
+use crate::{
+    CowValue, Entry, EntryHandle, EntryRef, EvictPolicy, FbrCache, PromotionPolicy, Region,
+};
+use std::{
+    borrow::Borrow,
+    collections::{HashMap, TryReserveError},
+    hash::Hash,
+    ops::Deref,
+};
+
+/// [`FbrCache`] with its capacity fixed at compile time.
+///
+/// The region sizes are available as constants and the requirements on
+/// `CAP` and `C_MAX` are checked during compilation:
+///
+/// ```
+/// use fbr_cache::FbrCacheN;
+///
+/// let mut cache = FbrCacheN::<u32, &str, 16, 8>::new();
+/// cache.put(1, "hello");
+/// assert_eq!(cache.capacity(), 16);
+/// assert_eq!(FbrCacheN::<u32, &str, 16, 8>::MID, 4);
+/// ```
+///
+/// ```compile_fail
+/// use fbr_cache::FbrCacheN;
+///
+/// let cache = FbrCacheN::<u32, &str, 2, 8>::new();
+/// ```
+///
+/// The queries of [`FbrCache`] are available through `Deref`, its mutating methods are
+/// forwarded except those that would change the capacity ([`FbrCache::set_capacity`],
+/// [`FbrCache::set_hard_capacity`] and [`FbrCache::set_grow_on_demand`]) and
+/// [`FbrCache::on_resize`], which consequently would never be called:
+///
+/// ```compile_fail
+/// use fbr_cache::FbrCacheN;
+///
+/// let mut cache = FbrCacheN::<u32, &str, 16, 8>::new();
+/// cache.set_capacity(32);
+/// ```
+pub struct FbrCacheN<K, V, const CAP: usize, const C_MAX: usize>(FbrCache<K, V, C_MAX>);
+
+impl<K, V, const CAP: usize, const C: usize> FbrCacheN<K, V, CAP, C> {
+    /// Number of items in the “new” region when the cache is full.
+    pub const MID: usize = CAP * 3 / 10;
+    /// Number of items in front of the “old” region when the cache is full.
+    pub const OLD: usize = CAP * 3 / 4;
+
+    const VALID: () = {
+        assert!(CAP >= 4, "capacity must be at least 4");
+        assert!(C >= 2, "C_MAX must be at least 2");
+    };
+
+    /// Unwrap the underlying runtime-sized cache.
+    pub fn into_inner(self) -> FbrCache<K, V, C> {
+        self.0
+    }
+
+    /// See [`FbrCache::clear`].
+    pub fn clear(&mut self) {
+        self.0.clear()
+    }
+
+    /// See [`FbrCache::clear_and_reset_stats`].
+    pub fn clear_and_reset_stats(&mut self) {
+        self.0.clear_and_reset_stats()
+    }
+
+    /// See [`FbrCache::set_auto_age_threshold`].
+    pub fn set_auto_age_threshold(&mut self, interval: Option<u64>) {
+        self.0.set_auto_age_threshold(interval)
+    }
+
+    /// See [`FbrCache::set_freq_decay_on_miss`].
+    pub fn set_freq_decay_on_miss(&mut self, weight: usize) {
+        self.0.set_freq_decay_on_miss(weight)
+    }
+
+    /// See [`FbrCache::set_region_aging`].
+    pub fn set_region_aging(&mut self, region: Region, shift: u32) {
+        self.0.set_region_aging(region, shift)
+    }
+
+    /// See [`FbrCache::reset_stats`].
+    pub fn reset_stats(&mut self) {
+        self.0.reset_stats()
+    }
+
+    /// See [`FbrCache::set_evict_policy`].
+    pub fn set_evict_policy(&mut self, policy: EvictPolicy) {
+        self.0.set_evict_policy(policy)
+    }
+
+    /// See [`FbrCache::set_scan_limit`].
+    pub fn set_scan_limit(&mut self, limit: usize) {
+        self.0.set_scan_limit(limit)
+    }
+
+    /// See [`FbrCache::set_overwrite_on_put`].
+    pub fn set_overwrite_on_put(&mut self, overwrite: bool) {
+        self.0.set_overwrite_on_put(overwrite)
+    }
+
+    /// See [`FbrCache::set_promotion_policy`].
+    pub fn set_promotion_policy(&mut self, policy: PromotionPolicy) {
+        self.0.set_promotion_policy(policy)
+    }
+
+    /// See [`FbrCache::set_admission`].
+    pub fn set_admission<F: Fn(&K) -> bool + Send + Sync + 'static>(&mut self, f: F) {
+        self.0.set_admission(f)
+    }
+
+    /// See [`FbrCache::on_insert`].
+    pub fn on_insert<F: FnMut(&K, &V, bool) + Send + Sync + 'static>(&mut self, f: F) {
+        self.0.on_insert(f)
+    }
+}
+
+impl<K: Hash + Eq + Clone, V, const CAP: usize, const C: usize> FbrCacheN<K, V, CAP, C> {
+    /// Create a new cache with the default aging threshold.
+    pub fn new() -> Self {
+        Self::with_age_threshold(100)
+    }
+
+    /// Create a new cache with the given aging threshold.
+    pub fn with_age_threshold(age_threshold: usize) -> Self {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::VALID;
+        Self(FbrCache::with_age_threshold(CAP, age_threshold))
+    }
+
+    /// See [`FbrCache::try_reserve`].
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        self.0.try_reserve(additional)
+    }
+
+    /// See [`FbrCache::put`].
+    pub fn put(&mut self, key: K, value: V) -> bool {
+        self.0.put(key, value)
+    }
+
+    /// See [`FbrCache::set_max_count`].
+    pub fn set_max_count(&mut self, max_count: usize) {
+        self.0.set_max_count(max_count)
+    }
+
+    /// See [`FbrCache::put_if_absent`].
+    pub fn put_if_absent(&mut self, key: K, value: V) -> bool {
+        self.0.put_if_absent(key, value)
+    }
+
+    /// See [`FbrCache::put_prio`].
+    pub fn put_prio(&mut self, key: K, value: V) -> bool {
+        self.0.put_prio(key, value)
+    }
+
+    /// See [`FbrCache::put_prio_n`].
+    pub fn put_prio_n(&mut self, key: K, value: V, initial_count: usize) -> bool {
+        self.0.put_prio_n(key, value, initial_count)
+    }
+
+    /// See [`FbrCache::put_ref`].
+    pub fn put_ref(&mut self, key: K, value: V) -> &V {
+        self.0.put_ref(key, value)
+    }
+
+    /// See [`FbrCache::extend_prio`].
+    pub fn extend_prio(&mut self, items: impl IntoIterator<Item = (K, V)>) {
+        self.0.extend_prio(items)
+    }
+
+    /// See [`FbrCache::prepopulate`].
+    pub fn prepopulate<I, F>(&mut self, keys: I, f: F)
+    where
+        I: IntoIterator<Item = K>,
+        F: FnMut(&K) -> V,
+    {
+        self.0.prepopulate(keys, f)
+    }
+
+    /// See [`FbrCache::pin`].
+    pub fn pin(&mut self, key: &K) -> bool {
+        self.0.pin(key)
+    }
+
+    /// See [`FbrCache::unpin`].
+    pub fn unpin(&mut self, key: &K) -> bool {
+        self.0.unpin(key)
+    }
+
+    /// See [`FbrCache::get`].
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        self.0.get(key)
+    }
+
+    /// See [`FbrCache::get_cow`].
+    pub fn get_cow(&mut self, key: &K) -> Option<CowValue<'_, V>>
+    where
+        V: Clone,
+    {
+        self.0.get_cow(key)
+    }
+
+    /// See [`FbrCache::get_cloned_many`].
+    pub fn get_cloned_many(&mut self, keys: &[K]) -> HashMap<K, V>
+    where
+        V: Clone,
+    {
+        self.0.get_cloned_many(keys)
+    }
+
+    /// See [`FbrCache::get_unchecked`].
+    ///
+    /// # Safety
+    ///
+    /// The same requirements as for [`FbrCache::get_unchecked`] apply.
+    pub unsafe fn get_unchecked(&mut self, handle: &EntryHandle<K, V>) -> &V {
+        self.0.get_unchecked(handle)
+    }
+
+    /// See [`FbrCache::get_or_default_mut`].
+    pub fn get_or_default_mut(&mut self, key: K) -> &mut V
+    where
+        V: Default,
+    {
+        self.0.get_or_default_mut(key)
+    }
+
+    /// See [`FbrCache::get_mut_or_insert_with`].
+    pub fn get_mut_or_insert_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &mut V {
+        self.0.get_mut_or_insert_with(key, f)
+    }
+
+    /// See [`FbrCache::get_or_insert_with_status`].
+    pub fn get_or_insert_with_status<F: FnOnce() -> V>(&mut self, key: K, f: F) -> (&V, bool) {
+        self.0.get_or_insert_with_status(key, f)
+    }
+
+    /// See [`FbrCache::peek_or_insert_with`].
+    pub fn peek_or_insert_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &V {
+        self.0.peek_or_insert_with(key, f)
+    }
+
+    /// See [`FbrCache::get_or_recompute`].
+    pub fn get_or_recompute<P, F>(&mut self, key: K, is_fresh: P, recompute: F) -> &V
+    where
+        P: FnOnce(&V) -> bool,
+        F: FnOnce() -> V,
+    {
+        self.0.get_or_recompute(key, is_fresh, recompute)
+    }
+
+    /// See [`FbrCache::replace_with`].
+    pub fn replace_with<F: FnOnce(V) -> V>(&mut self, key: &K, f: F) -> bool {
+        self.0.replace_with(key, f)
+    }
+
+    /// See [`FbrCache::set_value`].
+    pub fn set_value(&mut self, key: &K, value: V) -> Result<V, V> {
+        self.0.set_value(key, value)
+    }
+
+    /// See [`FbrCache::touch_many`].
+    pub fn touch_many(&mut self, keys: &[K]) -> usize {
+        self.0.touch_many(keys)
+    }
+
+    /// See [`FbrCache::touch_prio`].
+    pub fn touch_prio(&mut self, key: &K) -> bool {
+        self.0.touch_prio(key)
+    }
+
+    /// See [`FbrCache::entry`].
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, C> {
+        self.0.entry(key)
+    }
+
+    /// See [`FbrCache::entry_ref`].
+    pub fn entry_ref<'a, 'q, Q>(&'a mut self, key: &'q Q) -> EntryRef<'a, 'q, K, Q, V, C>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.0.entry_ref(key)
+    }
+
+    /// See [`FbrCache::remove`].
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.0.remove(key)
+    }
+
+    /// See [`FbrCache::remove_if`].
+    pub fn remove_if<F: FnOnce(&K, &V) -> bool>(&mut self, key: &K, pred: F) -> Option<V> {
+        self.0.remove_if(key, pred)
+    }
+
+    /// See [`FbrCache::take`].
+    pub fn take(&mut self, key: &K) -> Option<V> {
+        self.0.take(key)
+    }
+
+    /// See [`FbrCache::set_tombstones`].
+    pub fn set_tombstones(&mut self, limit: usize) {
+        self.0.set_tombstones(limit)
+    }
+
+    /// See [`FbrCache::rehome`].
+    pub fn rehome(&mut self, key: &K, dest: &mut Self) -> bool {
+        self.0.rehome(key, &mut dest.0)
+    }
+
+    /// See [`FbrCache::merge_with`].
+    pub fn merge_with<F: FnMut(&K, V, V) -> V>(&mut self, other: Self, resolve: F) {
+        self.0.merge_with(other.0, resolve)
+    }
+
+    /// See [`FbrCache::drain_region`].
+    pub fn drain_region(&mut self, region: Region) -> Vec<(K, V, usize)> {
+        self.0.drain_region(region)
+    }
+
+    /// See [`FbrCache::retain_regions`].
+    pub fn retain_regions(&mut self, keep: &[Region]) {
+        self.0.retain_regions(keep)
+    }
+
+    /// See [`FbrCache::retain_mut`].
+    pub fn retain_mut<F: FnMut(&K, &mut V) -> bool>(&mut self, f: F) {
+        self.0.retain_mut(f)
+    }
+
+    /// See [`FbrCache::split_off_region`].
+    pub fn split_off_region(&mut self, region: Region) -> FbrCache<K, V, C> {
+        self.0.split_off_region(region)
+    }
+
+    /// See [`FbrCache::split_at_rank`].
+    pub fn split_at_rank(&mut self, rank: usize) -> FbrCache<K, V, C> {
+        self.0.split_at_rank(rank)
+    }
+
+    /// See [`FbrCache::demote`].
+    pub fn demote(&mut self, min_items: usize) -> Vec<(K, V, usize)> {
+        self.0.demote(min_items)
+    }
+
+    /// See [`FbrCache::demote_weighted`].
+    pub fn demote_weighted<F>(&mut self, min_weight: usize, weight: F) -> Vec<(K, V, usize)>
+    where
+        F: FnMut(&K, &V) -> usize,
+    {
+        self.0.demote_weighted(min_weight, weight)
+    }
+
+    /// See [`FbrCache::prune_to`].
+    pub fn prune_to(&mut self, target_len: usize) -> usize {
+        self.0.prune_to(target_len)
+    }
+
+    /// See [`FbrCache::clear_except_top`].
+    pub fn clear_except_top(&mut self, keep: usize) -> usize {
+        self.0.clear_except_top(keep)
+    }
+
+    /// See [`FbrCache::reset_counts`].
+    pub fn reset_counts(&mut self) {
+        self.0.reset_counts()
+    }
+}
+
+impl<K: Hash + Eq + Clone, V, const CAP: usize, const C: usize> Default
+    for FbrCacheN<K, V, CAP, C>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V, const CAP: usize, const C: usize> Deref for FbrCacheN<K, V, CAP, C> {
+    type Target = FbrCache<K, V, C>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<K, V, const CAP: usize, const C: usize> std::fmt::Debug for FbrCacheN<K, V, CAP, C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
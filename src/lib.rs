@@ -1,11 +1,27 @@
 #![doc = include_str!("../README.md")]
 
-use intrusive_collections::{intrusive_adapter, LinkedList, LinkedListLink, UnsafeRef};
-use std::{collections::HashMap, hash::Hash, ptr::null};
+use intrusive_collections::{
+    intrusive_adapter, linked_list, LinkedList, LinkedListLink, UnsafeRef,
+};
+use std::{
+    borrow::Borrow,
+    collections::{HashMap, HashSet, TryReserveError, VecDeque},
+    hash::Hash,
+    ptr::null,
+};
 
+mod entry;
+mod fixed;
+mod set;
+mod state;
 #[cfg(test)]
 mod tests;
 
+pub use entry::{CowValue, Entry, EntryRef, OccupiedEntry, VacantEntry, VacantEntryRef};
+pub use fixed::FbrCacheN;
+pub use set::FbrSet;
+pub use state::{CacheConfig, CacheState, ConfigError, FbrConfig};
+
 /// Region in which a cache entry currently lives
 ///
 /// New inhibits frequency count (counting one “run” as 1),
@@ -17,12 +33,161 @@ pub enum Region {
     Old,
 }
 
+impl Region {
+    /// All regions, from most to least recent.
+    pub const ALL: [Region; 3] = [Region::New, Region::Middle, Region::Old];
+
+    /// The next less recent region, if any.
+    pub fn next(self) -> Option<Region> {
+        match self {
+            Region::New => Some(Region::Middle),
+            Region::Middle => Some(Region::Old),
+            Region::Old => None,
+        }
+    }
+}
+
+/// Whether a key is cached, as reported by [`FbrCache::status`]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum EntryStatus {
+    /// The key is in the cache.
+    Present,
+    /// The key was recently removed with [`FbrCache::take`] while tombstones are enabled.
+    RecentlyRemoved,
+    /// The key is not in the cache, and not known to have been taken recently.
+    Absent,
+}
+
+/// Strategy for choosing the item to evict when making room for a new one
+///
+/// All policies only consider the “old” region; if it holds no suitable
+/// candidate, the least recently used item is evicted.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum EvictPolicy {
+    /// Lowest usage count first, least recently used among equals.
+    ///
+    /// This is the default.
+    #[default]
+    LowestCount,
+    /// Least recently used among the items with a usage count below the given limit.
+    LruBelow(usize),
+    /// Least recently used, ignoring usage counts.
+    Lru,
+}
+
+/// Strategy for deciding whether a cache hit moves the item to the front
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum PromotionPolicy {
+    /// Every hit makes the item the most recently used one.
+    ///
+    /// This is the default.
+    #[default]
+    Always,
+    /// Hits on items in the “new” region leave the recency order unchanged.
+    ///
+    /// Such items are recent already and do not gain usage counts either, so moving
+    /// them only causes churn under scan-heavy workloads.
+    NotInNew,
+}
+
+/// Why the current [`EvictPolicy`] would choose a particular item, see [`EvictExplanation`]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum EvictReason {
+    /// Least recent item in the “old” region of the count chain with the given index.
+    OldChain(usize),
+    /// Lowest count in the “old” region, which only holds items with counts of `C_MAX` and above.
+    OldOverflow,
+    /// Least recent item in the “old” region with a count below the [`EvictPolicy::LruBelow`] limit.
+    OldBelowLimit,
+    /// No suitable candidate in the “old” region, so the least recently used item is chosen.
+    LruFallback,
+}
+
+/// The item the cache would evict next and why, as reported by [`FbrCache::explain_eviction`]
+#[cfg(debug_assertions)]
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct EvictExplanation<K> {
+    pub key: K,
+    pub count: usize,
+    pub region: Region,
+    pub reason: EvictReason,
+}
+
+/// Violation of an internal consistency property, as reported by [`FbrCache::check_invariants`]
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum InvariantError {
+    /// The hash map and the recency list hold different numbers of items.
+    LengthMismatch { hash: usize, lru: usize },
+    /// The item at the given recency position is not the one the hash map points to.
+    HashMismatch { position: usize },
+    /// An item with the given usage count is linked into the wrong count chain (`C_MAX` for overflow).
+    ChainMismatch { chain: usize, count: usize },
+    /// The count chains and the overflow chain together hold a different number of items than the cache.
+    ChainLength { chains: usize, expected: usize },
+    /// The item at the given recency position is tagged with the wrong region.
+    RegionMismatch {
+        position: usize,
+        expected: Region,
+        found: Region,
+    },
+    /// The boundary pointer for the given region does not point at its first item.
+    BoundaryMismatch(Region),
+    /// The recorded length of the given count chain is wrong.
+    OccupancyMismatch { chain: usize },
+    /// The item at the given recency position is out of recency order in its count chain.
+    ChainOrder { position: usize },
+}
+
+impl std::fmt::Display for InvariantError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InvariantError::LengthMismatch { hash, lru } => {
+                write!(f, "hash map holds {} items but LRU list {}", hash, lru)
+            }
+            InvariantError::HashMismatch { position } => {
+                write!(f, "hash map entry mismatch at position {}", position)
+            }
+            InvariantError::ChainMismatch { chain, count } => {
+                write!(f, "item with count {} found in chain {}", count, chain)
+            }
+            InvariantError::ChainLength { chains, expected } => {
+                write!(f, "chains hold {} items, expected {}", chains, expected)
+            }
+            InvariantError::RegionMismatch {
+                position,
+                expected,
+                found,
+            } => write!(
+                f,
+                "item at position {} is in region {:?}, expected {:?}",
+                position, found, expected
+            ),
+            InvariantError::BoundaryMismatch(region) => {
+                write!(f, "boundary of region {:?} is misplaced", region)
+            }
+            InvariantError::OccupancyMismatch { chain } => {
+                write!(f, "chain {} has a wrongly recorded length", chain)
+            }
+            InvariantError::ChainOrder { position } => {
+                write!(
+                    f,
+                    "item at position {} is out of order in its chain",
+                    position
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for InvariantError {}
+
 #[derive(Debug)]
 struct FbrEntry<K, V> {
     lru: LinkedListLink,
     chain: LinkedListLink,
     count: usize,
     region: Region,
+    pinned: bool,
     key: K,
     value: V,
 }
@@ -34,6 +199,7 @@ impl<K, V> FbrEntry<K, V> {
             chain: Default::default(),
             count: 0,
             region: Region::New,
+            pinned: false,
             key,
             value,
         }
@@ -42,28 +208,45 @@ impl<K, V> FbrEntry<K, V> {
         let this = unsafe { &mut *UnsafeRef::into_raw(ptr.clone()) };
         this.count = 0;
         this.region = Region::New;
+        this.pinned = false;
         this.key = key;
         this.value = value;
     }
-    pub fn access(ptr: &UnsafeRef<Self>) -> usize {
+    pub fn access(ptr: &UnsafeRef<Self>, max_count: usize) -> usize {
         let this = unsafe { &mut *UnsafeRef::into_raw(ptr.clone()) };
         let count = this.count;
-        if this.region != Region::New {
+        if this.region != Region::New && count < max_count {
             this.count += 1;
         }
         this.region = Region::New;
         count
     }
-    pub fn bump(ptr: &UnsafeRef<Self>) {
+    pub fn pin(ptr: &UnsafeRef<Self>, pinned: bool) {
+        let this = unsafe { &mut *UnsafeRef::into_raw(ptr.clone()) };
+        this.pinned = pinned;
+    }
+    pub fn bump(ptr: &UnsafeRef<Self>, by: usize, max_count: usize) {
+        let this = unsafe { &mut *UnsafeRef::into_raw(ptr.clone()) };
+        this.count = this.count.saturating_add(by).min(max_count);
+    }
+    pub fn clamp(ptr: &UnsafeRef<Self>, max_count: usize) -> usize {
         let this = unsafe { &mut *UnsafeRef::into_raw(ptr.clone()) };
-        this.count += 1;
+        let count = this.count;
+        this.count = count.min(max_count);
+        count - this.count
     }
-    pub fn age(ptr: &UnsafeRef<Self>) -> usize {
+    pub fn age(ptr: &UnsafeRef<Self>, shift: u32) -> usize {
         let this = unsafe { &mut *UnsafeRef::into_raw(ptr.clone()) };
         let count = this.count;
-        this.count /= 2;
+        this.count = count.checked_shr(shift).unwrap_or(0);
         count - this.count
     }
+    pub unsafe fn value<'a>(ptr: &UnsafeRef<Self>) -> &'a V {
+        &(*UnsafeRef::into_raw(ptr.clone())).value
+    }
+    pub unsafe fn value_mut<'a>(ptr: &UnsafeRef<Self>) -> &'a mut V {
+        &mut (*UnsafeRef::into_raw(ptr.clone())).value
+    }
     pub fn region(ptr: &UnsafeRef<Self>, region: Region) {
         let this = unsafe { &mut *UnsafeRef::into_raw(ptr.clone()) };
         this.region = region;
@@ -76,38 +259,179 @@ intrusive_adapter!(ListChain<K, V> = UnsafeRef<FbrEntry<K, V>>: FbrEntry<K, V> {
 /// Cache with frequency-based replacement strategy.
 ///
 /// Items are held in recently-used order, with the front 30% of the list
-/// designated as “new” space and the back 25% as “old” space. Each cache hit
+/// designated as “new” space and the back 25% as “old” space. These proportions
+/// refer to the current number of items, so that usage counting and frequency-based
+/// eviction already work while the cache is filling up or held below capacity (up to
+/// version 0.1.1 the regions only formed once the cache was 30% resp. 75% full, so hits
+/// during the filling phase now leave higher usage counts behind). Each cache hit
 /// (via [`Self::get`] or [`Self::put`]) moves the item in question to the front
 /// of the list and increments the usage count if the item was not in the “new”
-/// space before.
+/// space before; [`Self::set_promotion_policy`] can skip the move for “new” items.
 ///
 /// Usage counts are periodically aged (halved) to prevent items that were popular
 /// in the past from staying in the cache forever. This happens when the average
 /// frequency count exceeds the `age_threshold` parameter.
 ///
-/// Eviction only removes “old” items: if there are some with usage count less
-/// than `C_MAX`, the least recent among the least-used ones is taken; otherwise
-/// the least recently used is evicted.
+/// Eviction only removes “old” items: the least recent among the least-used ones
+/// is taken, where counts of `C_MAX` and above are only considered if no lower
+/// count is present in this region. This default can be changed with
+/// [`Self::set_evict_policy`].
 ///
 /// The cache will allocate only during the initial filling phase, afterwards it
 /// reuses the heap allocations where values are held.
 ///
 /// ## Requirements
 ///
-/// - `C_MAX` must be at least 2
+/// - `C_MAX` must be at least 2 and at most 4096, which is checked during compilation
+///   (see [`Self::chains_bytes`] for the resulting size; use [`Self::set_max_count`]
+///   for a low ceiling and let counts above `C_MAX` share the overflow chain instead
+///   of choosing a huge `C_MAX`)
 /// - `capacity` must be at least 4
 /// - `age_threshold` must be at least 1
+///
+/// ```compile_fail
+/// let cache = fbr_cache::FbrCache::<u32, u32, 5000>::with_age_threshold(100, 10);
+/// ```
+///
+/// ```compile_fail
+/// let cache = fbr_cache::FbrCache::<u32, u32, 1>::with_age_threshold(100, 10);
+/// ```
 pub struct FbrCache<K, V, const C_MAX: usize> {
     hash: HashMap<K, UnsafeRef<FbrEntry<K, V>>>,
     lru: LinkedList<ListLru<K, V>>,
     chains: [LinkedList<ListChain<K, V>>; C_MAX],
+    /// items with counts of `C_MAX` and above, in recency order
+    overflow: LinkedList<ListChain<K, V>>,
+    /// lengths of the count chains, so that scans skip the empty ones
+    occupied: Occupancy,
+    /// position of the first “middle” item, 30% of the current length
     mid: usize,
     mid_boundary: Option<UnsafeRef<FbrEntry<K, V>>>,
+    /// position of the first “old” item, 75% of the current length
     old: usize,
     old_boundary: Option<UnsafeRef<FbrEntry<K, V>>>,
     total_count: usize,
+    aged_total: usize,
+    age_events: u64,
+    evictions: u64,
+    inserts: u64,
+    promotions: u64,
+    demotions: u64,
+    hits: u64,
+    misses: u64,
+    been_full: bool,
     capacity: usize,
     age_threshold: usize,
+    max_count: usize,
+    evict_policy: EvictPolicy,
+    /// maximum number of non-empty count chains examined by [`EvictPolicy::LowestCount`]
+    scan_limit: usize,
+    promotion_policy: PromotionPolicy,
+    overwrite_on_put: bool,
+    admission: Option<Box<Admission<K>>>,
+    insert_observer: Option<Box<InsertObserver<K, V>>>,
+    resize_observer: Option<Box<ResizeObserver>>,
+    growth: Option<Growth<K>>,
+    /// number of items the cache may overshoot its capacity
+    slack: usize,
+    pinned: usize,
+    /// all cache hits, including those not counted as lookups
+    hit_count: u64,
+    auto_age: Option<AutoAge>,
+    /// weight of a lookup miss towards the aging threshold
+    miss_decay: usize,
+    /// weighted misses since the last aging pass
+    miss_pressure: usize,
+    /// number of halvings per aging pass, indexed by region
+    aging_shifts: [u32; 3],
+    /// keys recently removed by `take`, at most `tombstone_limit` of them
+    tombstones: Ghosts<K>,
+    tombstone_limit: usize,
+}
+
+/// A suggestion for the `C_MAX` parameter of an [`FbrCache`] with the given capacity.
+///
+/// Eviction looks for the lowest usage count in the “old” region, so only low counts need
+/// chains of their own; higher counts share the overflow chain, which is only scanned
+/// when no lower count is available there. Since the aging threshold scales with the
+/// capacity, counts in larger caches spread over a wider range before being halved, so
+/// the suggestion grows with the logarithm of the capacity: four chains per
+/// binary digit of the capacity, between 8 (the default of [`FbrCache::new`]) and 256.
+/// This keeps the chain heads (see [`FbrCache::chains_bytes`]) small compared to the
+/// items while rarely leaving the search to the overflow chain.
+///
+/// ```
+/// const C_MAX: usize = fbr_cache::recommended_c_max(10_000);
+/// let mut cache = fbr_cache::FbrCache::<u32, u32, C_MAX>::with_age_threshold(10_000, 100);
+/// cache.put(1, 1);
+/// ```
+pub const fn recommended_c_max(capacity: usize) -> usize {
+    let digits = (usize::BITS - capacity.leading_zeros()) as usize;
+    let c_max = 4 * digits;
+    if c_max < 8 {
+        8
+    } else if c_max > 256 {
+        256
+    } else {
+        c_max
+    }
+}
+
+/// Target cadence for adapting the aging threshold
+struct AutoAge {
+    interval: u64,
+    /// value of `hit_count` at the previous aging pass
+    since: u64,
+}
+
+type Admission<K> = dyn Fn(&K) -> bool + Send + Sync;
+type InsertObserver<K, V> = dyn FnMut(&K, &V, bool) + Send + Sync;
+type ResizeObserver = dyn FnMut(usize, usize) + Send + Sync;
+
+/// Recently evicted keys, used for deciding when to grow the capacity
+struct Growth<K> {
+    max_capacity: usize,
+    ghosts: Ghosts<K>,
+}
+
+/// Bounded set of keys that are no longer cached, forgetting the oldest ones first
+struct Ghosts<K> {
+    keys: HashSet<K>,
+    order: VecDeque<K>,
+}
+
+impl<K: Hash + Eq + Clone> Ghosts<K> {
+    fn new() -> Self {
+        Self {
+            keys: HashSet::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    fn contains(&self, key: &K) -> bool {
+        self.keys.contains(key)
+    }
+
+    fn remember(&mut self, key: &K, limit: usize) {
+        if self.keys.insert(key.clone()) {
+            self.order.push_back(key.clone());
+        }
+        self.truncate(limit);
+    }
+
+    fn forget(&mut self, key: &K) {
+        if self.keys.remove(key) {
+            self.order.retain(|k| k != key);
+        }
+    }
+
+    fn truncate(&mut self, limit: usize) {
+        while self.order.len() > limit {
+            if let Some(key) = self.order.pop_front() {
+                self.keys.remove(&key);
+            }
+        }
+    }
 }
 
 impl<K, V, const C: usize> Drop for FbrCache<K, V, C> {
@@ -128,210 +452,2381 @@ impl<K, V, const C: usize> std::fmt::Debug for FbrCache<K, V, C> {
 }
 
 impl<K, V, const C: usize> FbrCache<K, V, C> {
+    const VALID_C: () = {
+        assert!(C >= 2, "C_MAX must be at least 2");
+        assert!(C <= 4096, "C_MAX must be at most 4096");
+    };
+
+    /// The number of bytes the per-count chain heads occupy within the cache struct.
+    pub const fn chains_bytes() -> usize {
+        std::mem::size_of::<[LinkedList<ListChain<K, V>>; C]>()
+    }
+
+    /// The number of bytes each item occupies beyond the inline size of its key and value.
+    ///
+    /// This covers the links, usage count and flags of the heap-allocated entry as well as
+    /// the slot in the key index, which holds a copy of the key and a pointer to the entry.
+    /// Spare capacity and control bytes of the index are not included.
+    pub const fn entry_overhead_bytes() -> usize {
+        std::mem::size_of::<FbrEntry<K, V>>() - std::mem::size_of::<K>() - std::mem::size_of::<V>()
+            + std::mem::size_of::<(K, UnsafeRef<FbrEntry<K, V>>)>()
+    }
+
+    /// Estimate the memory used by all items, given the size of each key and value.
+    ///
+    /// `sizer` shall return the full size of an item, i.e. the inline size of key and
+    /// value plus whatever they own on the heap; the result adds
+    /// [`Self::entry_overhead_bytes`] per item. Note that the key copy in the index is
+    /// only counted with its inline size. This is a one-off measurement taking time linear
+    /// in the number of items.
+    pub fn estimated_bytes(&self, sizer: impl Fn(&K, &V) -> usize) -> usize {
+        self.lru.iter().fold(0, |total, cde| {
+            total
+                .saturating_add(sizer(&cde.key, &cde.value))
+                .saturating_add(Self::entry_overhead_bytes())
+        })
+    }
+
     /// The number of items currently in the cache.
     pub fn len(&self) -> usize {
         self.hash.len()
     }
 
+    /// The maximum number of items the cache holds.
+    ///
+    /// With a [`Self::hard_capacity`] above this value, it is the soft limit the cache
+    /// returns to after overshooting.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The number of items the cache may temporarily hold, see [`Self::set_hard_capacity`].
+    pub fn hard_capacity(&self) -> usize {
+        self.capacity.saturating_add(self.slack)
+    }
+
+    /// Allow the cache to grow beyond its capacity up to `hard_capacity` items.
+    ///
+    /// Below the hard limit new items are admitted without evicting anything. When the
+    /// hard limit is reached, items are evicted in one batch until the cache is back at
+    /// its capacity (including the new item), which amortizes eviction and boundary work
+    /// over many insertions. Memory use overshoots by at most `hard_capacity - capacity`
+    /// entries, and all but one of the evicted allocations are freed instead of being
+    /// reused. A value not above the capacity restores the default of evicting one item
+    /// per insertion.
+    pub fn set_hard_capacity(&mut self, hard_capacity: usize) {
+        self.slack = hard_capacity.saturating_sub(self.capacity);
+    }
+
     /// Returns `true` if there are no items in the cache.
     pub fn is_empty(&self) -> bool {
         self.hash.is_empty()
     }
 
     /// Clears all items from the cache.
+    ///
+    /// Items are dropped in reverse recency order, least recently used first; this also
+    /// applies when dropping the cache. Statistics like [`Self::evictions`] are cumulative
+    /// and survive this call, use [`Self::clear_and_reset_stats`] to start a fresh
+    /// measurement window.
     pub fn clear(&mut self) {
-        self.lru.fast_clear();
         for chain in &mut self.chains {
             chain.fast_clear();
         }
+        self.overflow.fast_clear();
+        self.occupied.clear();
+        self.mid = 0;
         self.mid_boundary = None;
+        self.old = 0;
         self.old_boundary = None;
         self.total_count = 0;
-        for (_, cde) in self.hash.drain() {
+        self.aged_total = 0;
+        self.miss_pressure = 0;
+        self.pinned = 0;
+        self.hash.clear();
+        while let Some(cde) = self.lru.pop_back() {
             unsafe { UnsafeRef::into_box(cde) };
         }
     }
 
-    /// An iterator over all currently held items together with their usage count and region.
-    pub fn iter(&self) -> impl Iterator<Item = (&K, &V, usize, Region)> {
-        self.lru
-            .iter()
-            .map(|cde| (&cde.key, &cde.value, cde.count, cde.region))
+    /// Clears all items from the cache and zeroes all statistics counters.
+    pub fn clear_and_reset_stats(&mut self) {
+        self.clear();
+        self.reset_stats();
     }
-}
 
-impl<K: Hash + Eq + Clone, V> FbrCache<K, V, 8> {
-    /// Create a new cache with the given capacity and aging threshold.
-    pub fn new(capacity: usize) -> Self {
-        Self::with_age_threshold(capacity, 100)
-    }
-}
-impl<K: Hash + Eq + Clone, V, const C: usize> FbrCache<K, V, C> {
-    /// Create a new cache with the given capacity and aging threshold.
-    pub fn with_age_threshold(capacity: usize, age_threshold: usize) -> Self {
-        Self {
-            hash: Default::default(),
-            lru: Default::default(),
-            chains: [(); C].map(|_| Default::default()),
-            mid: capacity * 3 / 10,
-            mid_boundary: None,
-            old: capacity * 3 / 4,
-            old_boundary: None,
-            total_count: Default::default(),
-            capacity,
-            age_threshold: capacity.saturating_mul(age_threshold),
+    /// Remove all entries, returning their allocations in recency order (most recent first).
+    fn take_entries(&mut self) -> Vec<Box<FbrEntry<K, V>>> {
+        for chain in &mut self.chains {
+            chain.clear();
+        }
+        self.overflow.clear();
+        self.occupied.clear();
+        self.mid = 0;
+        self.mid_boundary = None;
+        self.old = 0;
+        self.old_boundary = None;
+        self.total_count = 0;
+        self.aged_total = 0;
+        self.miss_pressure = 0;
+        self.pinned = 0;
+        let mut entries = Vec::with_capacity(self.hash.len());
+        self.hash.clear();
+        while let Some(cde) = self.lru.pop_front() {
+            entries.push(unsafe { UnsafeRef::into_box(cde) });
         }
+        entries
     }
 
-    /// Put the given item into the cache, evicting another item if necessary.
+    /// The sum of all usage counts beyond which aging happens.
     ///
-    /// This is usually called after finding no cached value for a key and computing said value.
-    pub fn put(&mut self, key: K, value: V) {
-        if self.get(&key).is_some() {
-            return;
-        }
-        self.insert(key, value, false);
+    /// This is the per-slot threshold multiplied by the capacity unless the cache was
+    /// created using [`FbrCache::with_absolute_age_threshold`].
+    pub fn effective_age_threshold(&self) -> usize {
+        self.age_threshold
     }
 
-    /// Put the given item into the cache with elevated priority.
+    /// Let the aging threshold adapt so that aging happens about every `interval` cache hits.
     ///
-    /// This means that the item starts out with a usage count of one instead
-    /// of zero. For cyclic usage patterns this means that priority items will
-    /// accumulate in the “old” region since non-priority items are evicted
-    /// before them. As usual, this works best if only a small fraction of
-    /// items get priority.
-    pub fn put_prio(&mut self, key: K, value: V) {
-        if self.get(&key).is_some() {
-            return;
-        }
-        self.insert(key, value, true);
+    /// Hits are counted regardless of whether they increment a usage count, i.e. including
+    /// hits in the “new” region and puts of present keys. At each aging pass the rate of
+    /// usage count increments per hit since the previous pass is measured, and the threshold
+    /// is set to the halved sum of counts plus the number of increments expected during the
+    /// next `interval` hits at that rate. A stable workload thus ages at the target cadence
+    /// from the second pass on, a changing one after the following pass. `None` stops the
+    /// adaptation, keeping the current threshold.
+    pub fn set_auto_age_threshold(&mut self, interval: Option<u64>) {
+        self.auto_age = interval.map(|interval| AutoAge {
+            interval: interval.max(1),
+            since: self.hit_count,
+        });
     }
 
-    /// Retrieve the value for a given key
+    /// Let every lookup miss count as `weight` usage count increments towards the aging threshold.
     ///
-    /// This updates the usage count and recency, so it can be used to “ping” a
-    /// key in order to bring it to the front again.
-    pub fn get(&mut self, key: &K) -> Option<&V> {
-        if let Some(cde) = self.hash.get(key) {
-            let region = cde.region;
-            let old_count = FbrEntry::access(cde);
-            let new_count = cde.count;
-            switch_chain(old_count, new_count, &mut self.chains, cde);
-            unsafe {
-                let mut cursor = self.lru.cursor_mut_from_ptr(cde.as_ref());
-                if optr(&self.mid_boundary) == ptr(cde) {
-                    self.mid_boundary = cursor.peek_next().clone_pointer();
-                } else if optr(&self.old_boundary) == ptr(cde) {
-                    self.old_boundary = cursor.peek_next().clone_pointer();
-                }
-                cursor.remove();
-            };
-            self.lru.push_front(cde.clone());
-            move_boundaries(
-                region,
-                self.len(),
-                self.mid,
-                self.old,
-                &self.lru,
-                &mut self.mid_boundary,
-                &mut self.old_boundary,
-            );
+    /// This is a knob for scan resistance: a flood of misses for new keys then ages the
+    /// usage counts sooner, so that formerly hot items which are no longer accessed lose
+    /// their protection faster. Misses do not change [`Self::accesses_since_age`]. The
+    /// default weight of zero disables this.
+    pub fn set_freq_decay_on_miss(&mut self, weight: usize) {
+        self.miss_decay = weight;
+    }
 
-            // periodic aging
-            self.total_count += new_count - old_count;
-            if self.total_count > self.age_threshold {
-                for cde in self.lru.iter() {
-                    let ptr = unsafe { UnsafeRef::from_raw(cde) };
-                    let old_count = ptr.count;
-                    self.total_count -= FbrEntry::age(&ptr);
-                    switch_chain(old_count, ptr.count, &mut self.chains, &ptr);
-                }
-            }
+    /// The weight of lookup misses towards aging, see [`Self::set_freq_decay_on_miss`].
+    pub fn freq_decay_on_miss(&self) -> usize {
+        self.miss_decay
+    }
 
-            Some(&cde.value)
-        } else {
-            None
+    /// Set how strongly aging decays the usage counts of items in the given region.
+    ///
+    /// Each aging pass divides the counts in this region by `2^shift`, the default of 1
+    /// halving them. A shift of 0 leaves the region alone, e.g. sparing the “new” and
+    /// “middle” regions keeps recently promoted hot items intact while high counts of
+    /// stragglers in the “old” region decay, which improves scan resistance. If a pass
+    /// does not bring the sum of counts below the threshold this way, the spared counts
+    /// are halved after all, so that aging does not repeat on every access.
+    pub fn set_region_aging(&mut self, region: Region, shift: u32) {
+        self.aging_shifts[region as usize] = shift;
+    }
+
+    /// The number of halvings per aging pass for the given region, see [`Self::set_region_aging`].
+    pub fn region_aging(&self, region: Region) -> u32 {
+        self.aging_shifts[region as usize]
+    }
+
+    /// The number of usage count increments since the last aging pass.
+    ///
+    /// Aging happens once the sum of all usage counts exceeds the threshold, so this
+    /// together with [`Self::age_events`] helps with tuning the `age_threshold`.
+    pub fn accesses_since_age(&self) -> usize {
+        self.total_count.saturating_sub(self.aged_total)
+    }
+
+    /// The number of times usage counts have been aged (halved) so far.
+    pub fn age_events(&self) -> u64 {
+        self.age_events
+    }
+
+    /// The number of items evicted so far, either to make room or by [`Self::prune_to`] and
+    /// [`Self::clear_except_top`].
+    ///
+    /// Explicit removals like [`Self::remove`] are not counted.
+    pub fn evictions(&self) -> u64 {
+        self.evictions
+    }
+
+    /// The number of new items put into the cache so far, including those from [`Self::import_state`].
+    pub fn inserts(&self) -> u64 {
+        self.inserts
+    }
+
+    /// The expected number of inserts a new item survives in the cache, estimated from the statistics.
+    ///
+    /// The model is a new item that is never used again: it falls back by one position
+    /// whenever an item behind it moves to the front, which happens on every insert that
+    /// evicts and on every [promotion](Self::promotions), until it is evicted from the
+    /// back. That takes `len` such steps, so the estimate is `len * inserts / (evictions +
+    /// promotions)`; for pure churn this is the capacity, while frequently promoted hot
+    /// items make cold ones leave faster. The eviction policy preferring items with low
+    /// usage counts is not taken into account. Without any evictions (e.g. during the
+    /// filling phase, or after [`Self::reset_stats`]) the result is infinite.
+    pub fn estimated_lifetime(&self) -> f64 {
+        if self.evictions == 0 {
+            return f64::INFINITY;
         }
+        let steps = self.evictions as f64 + self.promotions as f64;
+        self.len() as f64 * self.inserts as f64 / steps
     }
 
-    fn insert(&mut self, key: K, value: V, prio: bool) {
-        let entry = if self.len() >= self.capacity {
-            let e = self.evict();
-            FbrEntry::reuse(&e, key.clone(), value);
-            e
-        } else {
-            UnsafeRef::from_box(Box::new(FbrEntry::new(key.clone(), value)))
-        };
-        if prio {
-            FbrEntry::bump(&entry);
+    /// The number of cache hits so far that moved an item from the “middle” or “old” region back into the “new” one.
+    pub fn promotions(&self) -> u64 {
+        self.promotions
+    }
+
+    /// The number of times so far an item has moved into the “old” region.
+    ///
+    /// Together with [`Self::promotions`] this shows whether the working set is stable
+    /// (few transitions per access) or churning through the cache.
+    pub fn demotions(&self) -> u64 {
+        self.demotions
+    }
+
+    /// The number of lookups that found their key, see [`Self::misses`].
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    /// The number of lookups that did not find their key.
+    ///
+    /// Lookups are [`Self::get`], [`Self::get_cow`], [`Self::get_cloned_many`] (once per
+    /// key), [`Self::get_or_default_mut`], [`Self::get_mut_or_insert_with`],
+    /// [`Self::get_or_insert_with_status`], [`Self::get_or_recompute`], [`Self::touch_prio`],
+    /// [`Self::entry`] and [`Self::entry_ref`], while [`Self::get_unchecked`] always counts
+    /// as a hit. Neither side-effect free queries nor [`Self::put`] and its variants,
+    /// [`Self::touch_many`] or [`Self::peek_or_insert_with`] are counted.
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    /// Reset all statistics counters like [`Self::evictions`] and [`Self::hits`] to zero.
+    pub fn reset_stats(&mut self) {
+        self.evictions = 0;
+        self.inserts = 0;
+        self.promotions = 0;
+        self.demotions = 0;
+        self.age_events = 0;
+        self.hits = 0;
+        self.misses = 0;
+    }
+
+    /// Returns `true` once the cache has been filled to capacity for the first time.
+    ///
+    /// This marks the end of the initial filling phase, after which new items reuse the
+    /// allocations of evicted ones. The flag is never reset, not even by [`Self::clear`].
+    pub fn has_been_full(&self) -> bool {
+        self.been_full
+    }
+
+    /// The policy used for choosing eviction victims.
+    pub fn evict_policy(&self) -> EvictPolicy {
+        self.evict_policy
+    }
+
+    /// Change the policy used for choosing eviction victims.
+    pub fn set_evict_policy(&mut self, policy: EvictPolicy) {
+        self.evict_policy = policy;
+    }
+
+    /// The maximum number of count chains searched for an eviction victim, see [`Self::set_scan_limit`].
+    pub fn scan_limit(&self) -> usize {
+        self.scan_limit
+    }
+
+    /// Bound the number of count chains [`EvictPolicy::LowestCount`] searches for a victim.
+    ///
+    /// Only non-empty chains are searched, lowest count first, with the overflow chain for
+    /// counts of `C_MAX` and above coming last. If none of the first `limit` chains holds
+    /// an “old” item, the least recently used item is evicted instead. This bounds the
+    /// latency of an eviction for large `C_MAX` at the price of occasionally evicting an
+    /// item with a higher usage count than necessary. The default is unbounded.
+    pub fn set_scan_limit(&mut self, limit: usize) {
+        self.scan_limit = limit;
+    }
+
+    /// Whether putting an existing key replaces its value, see [`Self::set_overwrite_on_put`].
+    pub fn overwrite_on_put(&self) -> bool {
+        self.overwrite_on_put
+    }
+
+    /// Choose whether putting an existing key replaces its value, like `HashMap::insert`.
+    ///
+    /// By default the old value is kept. Either way the put counts as a hit, updating
+    /// usage count and recency.
+    pub fn set_overwrite_on_put(&mut self, overwrite: bool) {
+        self.overwrite_on_put = overwrite;
+    }
+
+    /// The ceiling for usage counts, `usize::MAX` (i.e. unbounded) by default.
+    pub fn max_count(&self) -> usize {
+        self.max_count
+    }
+
+    /// The policy deciding whether cache hits move items to the front.
+    pub fn promotion_policy(&self) -> PromotionPolicy {
+        self.promotion_policy
+    }
+
+    /// Change the policy deciding whether cache hits move items to the front.
+    pub fn set_promotion_policy(&mut self, policy: PromotionPolicy) {
+        self.promotion_policy = policy;
+    }
+
+    /// The sum of the usage counts of all items in the given region.
+    pub fn frequency_sum(&self, region: Region) -> usize {
+        self.region_iter(region).map(|cde| cde.count).sum()
+    }
+
+    /// The usage count below or at which the fraction `q` of all items lie.
+    ///
+    /// This is the nearest-rank quantile, e.g. `0.5` yields the median and `1.0` the
+    /// maximum count; an empty cache yields 0. Counts below `C_MAX` are read from the
+    /// recorded lengths of the per-count chains in `O(C_MAX)` time, only higher counts
+    /// require selecting from the overflow chain.
+    ///
+    /// Panics if `q` is not within `0.0..=1.0`.
+    pub fn frequency_quantile(&self, q: f64) -> usize {
+        assert!((0.0..=1.0).contains(&q), "quantile {} not within 0..=1", q);
+        let len = self.len();
+        if len == 0 {
+            return 0;
         }
-        self.hash.insert(key, entry.clone());
-        self.lru.push_front(entry.clone());
-        move_boundaries(
-            Region::Old,
-            self.len(),
-            self.mid,
-            self.old,
-            &self.lru,
-            &mut self.mid_boundary,
-            &mut self.old_boundary,
-        );
-        self.chains[entry.count].push_front(entry);
+        let rank = ((q * len as f64).ceil() as usize).clamp(1, len);
+        let mut seen = 0;
+        for count in occupied_chains(&self.occupied) {
+            seen += self.occupied.len(count);
+            if seen >= rank {
+                return count;
+            }
+        }
+        let mut overflow = self
+            .overflow
+            .iter()
+            .map(|cde| cde.count)
+            .collect::<Vec<_>>();
+        let (_, nth, _) = overflow.select_nth_unstable(rank - seen - 1);
+        *nth
     }
 
-    fn evict(&mut self) -> UnsafeRef<FbrEntry<K, V>> {
-        let mut found = None;
-        for chain in &mut self.chains {
-            let ptr = chain.back().clone_pointer();
-            if let Some(cde) = ptr {
-                if cde.region == Region::Old {
-                    unsafe { chain.cursor_mut_from_ptr(cde.as_ref()) }.remove();
-                    found = Some(cde);
-                    break;
+    /// Suggest a capacity that would probably yield the given hit rate (within `0.0..=1.0`).
+    ///
+    /// This is a rough heuristic based on [`Self::hits`], [`Self::misses`] and the current
+    /// usage counts. It assumes that each item attracts hits in proportion to its usage
+    /// count plus one, and that the missed keys would behave like the coldest cached item
+    /// if they were cached. A target below the observed hit rate then yields the number
+    /// of hottest items accounting for the target share of the hits, a higher target adds
+    /// as many cold slots as needed to turn enough misses into hits. Without any hits
+    /// there is nothing to extrapolate from, so the current capacity is returned.
+    pub fn recommend_capacity(&self, target_hit_rate: f64) -> usize {
+        assert!(
+            (0.0..=1.0).contains(&target_hit_rate),
+            "hit rate {} not within 0..=1",
+            target_hit_rate
+        );
+        if self.hits == 0 || self.is_empty() {
+            return self.capacity;
+        }
+        let lookups = (self.hits + self.misses) as f64;
+        let hit_rate = self.hits as f64 / lookups;
+        let mut weights = self
+            .lru
+            .iter()
+            .map(|cde| cde.count as f64 + 1.0)
+            .collect::<Vec<_>>();
+        weights.sort_by(|a, b| b.total_cmp(a));
+        let total = weights.iter().sum::<f64>();
+        if target_hit_rate <= hit_rate {
+            let wanted = total * target_hit_rate / hit_rate;
+            let mut sum = 0.0;
+            for (n, weight) in weights.iter().enumerate() {
+                if sum >= wanted {
+                    return n;
                 }
+                sum += weight;
             }
+            self.len()
+        } else {
+            let per_slot = self.hits as f64 / total * weights[weights.len() - 1];
+            let missing = (target_hit_rate - hit_rate) * lookups;
+            let extra = (missing / per_slot).ceil() as usize;
+            self.len().saturating_add(extra)
         }
-        // in case old region didn’t contain anything in self.chains, evict LRU
-        let cde = found.unwrap_or_else(|| self.lru.back().clone_pointer().unwrap());
-        unsafe {
-            let mut cursor = self.lru.cursor_mut_from_ptr(cde.as_ref());
-            if optr(&self.mid_boundary) == ptr(&cde) {
-                self.mid_boundary = cursor.peek_next().clone_pointer();
-            } else if optr(&self.old_boundary) == ptr(&cde) {
-                self.old_boundary = cursor.peek_next().clone_pointer();
-            }
-            cursor.remove();
+    }
+
+    /// Items of the given region in recency order, most recent first.
+    fn region_iter(&self, region: Region) -> impl Iterator<Item = &FbrEntry<K, V>> {
+        let first = match region {
+            Region::New => self.lru.front().get(),
+            Region::Middle => self.mid_boundary.as_deref(),
+            Region::Old => self.old_boundary.as_deref(),
         };
-        self.hash.remove(&cde.key);
-        cde
+        self.entries_from(first)
+            .take_while(move |cde| cde.region == region)
     }
-}
 
-fn switch_chain<K, V, const C: usize>(
-    old_count: usize,
-    new_count: usize,
-    chains: &mut [LinkedList<ListChain<K, V>>; C],
-    cde: &UnsafeRef<FbrEntry<K, V>>,
-) {
-    if old_count < C {
-        unsafe { chains[old_count].cursor_mut_from_ptr(cde.as_ref()) }.remove();
+    /// Items in recency order, starting at the given one.
+    fn entries_from<'a>(
+        &'a self,
+        first: Option<&'a FbrEntry<K, V>>,
+    ) -> impl Iterator<Item = &'a FbrEntry<K, V>> + 'a {
+        std::iter::successors(first, move |cde| {
+            let mut cursor = unsafe { self.lru.cursor_from_ptr(*cde) };
+            cursor.move_next();
+            cursor.get()
+        })
     }
-    if new_count < C {
-        chains[new_count].push_front(cde.clone());
+
+    /// Install an admission predicate consulted before putting a new key into the cache.
+    ///
+    /// If the predicate returns `false`, the item is not cached and nothing is evicted.
+    /// This applies to [`Self::put`] as well as the priority variants like
+    /// [`Self::put_prio`]; methods that need to return a reference to the inserted value,
+    /// like [`Self::get_or_default_mut`], always admit. Keys already in the cache are not
+    /// affected.
+    pub fn set_admission<F: Fn(&K) -> bool + Send + Sync + 'static>(&mut self, f: F) {
+        self.admission = Some(Box::new(f));
     }
-}
 
-fn move_boundaries<K, V>(
-    from_region: Region,
-    len: usize,
-    mid: usize,
-    old: usize,
-    lru: &LinkedList<ListLru<K, V>>,
-    mid_boundary: &mut Option<UnsafeRef<FbrEntry<K, V>>>,
-    old_boundary: &mut Option<UnsafeRef<FbrEntry<K, V>>>,
-) {
-    if from_region > Region::New {
-        if let Some(mid) = mid_boundary {
+    /// Install an observer called for every item newly put into the cache.
+    ///
+    /// It receives key and value as well as whether this was a priority insertion with a
+    /// non-zero initial usage count (like [`Self::put_prio`]). Items refused by the admission
+    /// predicate and puts of keys already present are not reported.
+    pub fn on_insert<F: FnMut(&K, &V, bool) + Send + Sync + 'static>(&mut self, f: F) {
+        self.insert_observer = Some(Box::new(f));
+    }
+
+    /// Install an observer called with the old and new capacity whenever the capacity changes.
+    ///
+    /// This happens through [`Self::set_capacity`] and when growing on demand (see
+    /// [`Self::set_grow_on_demand`]), which allows keeping companion structures in sync.
+    pub fn on_resize<F: FnMut(usize, usize) + Send + Sync + 'static>(&mut self, f: F) {
+        self.resize_observer = Some(Box::new(f));
+    }
+
+    /// Recompute the region of every item and the boundary pointers from the recency order.
+    fn rebuild_boundaries(&mut self) {
+        self.mid = self.len() * 3 / 10;
+        self.old = self.len() * 3 / 4;
+        self.mid_boundary = None;
+        self.old_boundary = None;
+        let mut cursor = self.lru.front();
+        let mut idx = 0;
+        while let Some(cde) = cursor.clone_pointer() {
+            let region = if idx < self.mid {
+                Region::New
+            } else if idx < self.old {
+                Region::Middle
+            } else {
+                Region::Old
+            };
+            if idx == self.mid {
+                self.mid_boundary = Some(cde.clone());
+            }
+            if idx == self.old {
+                self.old_boundary = Some(cde.clone());
+            }
+            FbrEntry::region(&cde, region);
+            cursor.move_next();
+            idx += 1;
+        }
+    }
+
+    /// Move the region boundaries to their proportional positions after the length changed by one.
+    fn rebalance(&mut self) {
+        let len = self.len();
+        let (mid, old) = (len * 3 / 10, len * 3 / 4);
+        // positions move by at most one and both in the same direction; the boundary
+        // ahead moves first, so that an item shared by both ends up in the right region
+        let shift_mid = |this: &mut Self| {
+            if mid != this.mid {
+                let back = mid > this.mid;
+                shift_boundary(
+                    back,
+                    Region::New,
+                    Region::Middle,
+                    &this.lru,
+                    &mut this.mid_boundary,
+                );
+            }
+        };
+        let shift_old = |this: &mut Self| {
+            if old != this.old {
+                let back = old > this.old;
+                shift_boundary(
+                    back,
+                    Region::Middle,
+                    Region::Old,
+                    &this.lru,
+                    &mut this.old_boundary,
+                );
+                if !back && this.old_boundary.is_some() {
+                    this.demotions += 1;
+                }
+            }
+        };
+        if mid > self.mid || old > self.old {
+            shift_old(self);
+            shift_mid(self);
+        } else {
+            shift_mid(self);
+            shift_old(self);
+        }
+        self.mid = mid;
+        self.old = old;
+    }
+
+    /// An iterator over all currently held items together with their usage count and region.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V, usize, Region)> {
+        self.lru
+            .iter()
+            .map(|cde| (&cde.key, &cde.value, cde.count, cde.region))
+    }
+
+    /// Call `f` for all currently held items with their usage count and region, most recent first.
+    ///
+    /// This visits the same items as [`Self::iter`], but as an internal loop, which can be
+    /// more convenient for accumulating statistics. Nothing is allocated or changed.
+    pub fn for_each<F: FnMut(&K, &V, usize, Region)>(&self, mut f: F) {
+        for cde in self.lru.iter() {
+            f(&cde.key, &cde.value, cde.count, cde.region);
+        }
+    }
+
+    /// The region of the item at the given recency rank, `None` if there is no such item.
+    ///
+    /// This is computed from the region boundaries and thus takes constant time.
+    pub fn region_at_rank(&self, rank: usize) -> Option<Region> {
+        if rank >= self.len() {
+            None
+        } else if self.old_boundary.is_some() && rank >= self.old {
+            Some(Region::Old)
+        } else if self.mid_boundary.is_some() && rank >= self.mid {
+            Some(Region::Middle)
+        } else {
+            Some(Region::New)
+        }
+    }
+
+    /// Like [`Self::iter`], but also yielding the recency rank (see [`Self::recency_rank`]) first.
+    pub fn iter_with_rank(&self) -> impl Iterator<Item = (usize, &K, &V, usize, Region)> {
+        self.iter()
+            .enumerate()
+            .map(|(rank, (key, value, count, region))| (rank, key, value, count, region))
+    }
+
+    /// Like [`Self::iter`], but returning a nameable iterator that can be cloned.
+    pub fn peek_iter(&self) -> FbrIter<'_, K, V> {
+        FbrIter {
+            inner: self.lru.iter(),
+        }
+    }
+
+    /// The `n` keys with the highest usage counts, hottest first.
+    ///
+    /// Among items with equal counts the more recently used ones come first.
+    pub fn hot_keys(&self, n: usize) -> Vec<&K> {
+        let mut entries = self.lru.iter().collect::<Vec<_>>();
+        entries.sort_by_key(|cde| std::cmp::Reverse(cde.count));
+        entries.into_iter().take(n).map(|cde| &cde.key).collect()
+    }
+
+    /// All keys with a score blending usage count and recency, highest score first.
+    ///
+    /// The score is `freq_weight * count + (1 - freq_weight) * recency`, where recency is
+    /// `(len - rank) / len` for the item at recency rank `rank`, i.e. 1.0 for the most
+    /// recent item, decreasing linearly towards the least recent one. Items with equal
+    /// scores keep their recency order.
+    pub fn iter_by_importance(&self, freq_weight: f64) -> Vec<(&K, f64)> {
+        let len = self.len() as f64;
+        let mut entries = self
+            .lru
+            .iter()
+            .enumerate()
+            .map(|(rank, cde)| {
+                let recency = (len - rank as f64) / len;
+                let score = freq_weight * cde.count as f64 + (1.0 - freq_weight) * recency;
+                (&cde.key, score)
+            })
+            .collect::<Vec<_>>();
+        entries.sort_by(|a, b| b.1.total_cmp(&a.1));
+        entries
+    }
+
+    /// The most recently used item, without updating usage count or recency.
+    pub fn front(&self) -> Option<(&K, &V)> {
+        self.lru.front().get().map(|cde| (&cde.key, &cde.value))
+    }
+
+    /// The least recently used item, without updating usage count or recency.
+    pub fn back(&self) -> Option<(&K, &V)> {
+        self.lru.back().get().map(|cde| (&cde.key, &cde.value))
+    }
+
+    /// The keys of all currently held items in recency order, most recent first.
+    ///
+    /// This is a cheaper alternative to [`Self::iter`] when only the ordering is of interest.
+    pub fn lru_order(&self) -> Vec<K>
+    where
+        K: Clone,
+    {
+        self.lru.iter().map(|cde| cde.key.clone()).collect()
+    }
+
+    /// Render the internal structure in Graphviz DOT format, for debugging.
+    ///
+    /// Items are nodes labelled with key and usage count, grouped into one cluster per
+    /// region and named `n0`, `n1`, … by recency rank. Solid edges follow the recency list,
+    /// dashed edges the count chains starting from the nodes `c0`, `c1`, … and `overflow`;
+    /// the boundary pointers are drawn as the nodes `mid` and `old`.
+    pub fn to_dot(&self) -> String
+    where
+        K: std::fmt::Debug,
+    {
+        use std::fmt::Write;
+
+        let rank = self
+            .lru
+            .iter()
+            .enumerate()
+            .map(|(rank, cde)| (cde as *const FbrEntry<K, V>, rank))
+            .collect::<HashMap<_, _>>();
+        let mut dot = String::from("digraph fbr_cache {\n    node [shape=box];\n");
+        for region in Region::ALL {
+            let _ = writeln!(dot, "    subgraph cluster_{:?} {{", region);
+            let _ = writeln!(dot, "        label=\"{:?}\";", region);
+            for (rank, cde) in self.lru.iter().enumerate() {
+                if cde.region == region {
+                    let label = format!("{:?} ({})", cde.key, cde.count).replace('"', "\\\"");
+                    let _ = writeln!(dot, "        n{} [label=\"{}\"];", rank, label);
+                }
+            }
+            dot.push_str("    }\n");
+        }
+        for rank in 1..self.len() {
+            let _ = writeln!(dot, "    n{} -> n{};", rank - 1, rank);
+        }
+        let chains = self
+            .chains
+            .iter()
+            .enumerate()
+            .map(|(c, l)| (format!("c{}", c), l));
+        for (name, chain) in chains.chain(Some((String::from("overflow"), &self.overflow))) {
+            let _ = writeln!(dot, "    {} [shape=ellipse];", name);
+            let mut prev = name;
+            for cde in chain.iter() {
+                let next = format!("n{}", rank[&(cde as *const _)]);
+                let _ = writeln!(dot, "    {} -> {} [style=dashed];", prev, next);
+                prev = next;
+            }
+        }
+        for (name, boundary) in [("mid", &self.mid_boundary), ("old", &self.old_boundary)] {
+            if let Some(cde) = boundary {
+                let _ = writeln!(dot, "    {} [shape=plaintext];", name);
+                let _ = writeln!(dot, "    {} -> n{} [style=dotted];", name, rank[&ptr(cde)]);
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// Iterator over the items of an [`FbrCache`] in recency order, see [`FbrCache::peek_iter`]
+///
+/// Cloning the iterator yields an independent copy starting at the current position.
+pub struct FbrIter<'a, K, V> {
+    inner: linked_list::Iter<'a, ListLru<K, V>>,
+}
+
+impl<'a, K, V> Iterator for FbrIter<'a, K, V> {
+    type Item = (&'a K, &'a V, usize, Region);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner
+            .next()
+            .map(|cde| (&cde.key, &cde.value, cde.count, cde.region))
+    }
+}
+
+impl<K, V> Clone for FbrIter<'_, K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+/// Token for accessing an item of an [`FbrCache`] without hashing its key, see [`FbrCache::handle`]
+///
+/// A handle stays valid as long as its item remains in the cache. It is invalidated by
+/// every operation that may take the item out, i.e. eviction to make room for a new
+/// item, explicit removal like [`FbrCache::remove`], clearing, and all methods that
+/// consume or rebuild the cache. Since evicted allocations are reused for new items,
+/// using a stale handle may silently access another item or freed memory.
+pub struct EntryHandle<K, V> {
+    ptr: *const FbrEntry<K, V>,
+}
+
+impl<K, V> Clone for EntryHandle<K, V> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<K, V> Copy for EntryHandle<K, V> {}
+
+impl<K: Hash + Eq + Clone, V> FbrCache<K, V, 8> {
+    /// Create a new cache with the given capacity and aging threshold.
+    pub fn new(capacity: usize) -> Self {
+        Self::with_age_threshold(capacity, 100)
+    }
+
+    /// Like [`Self::new`], but reserving room for `capacity` items up front.
+    ///
+    /// An allocation failure is reported instead of aborting the process. Note that
+    /// the entries themselves are still allocated one by one while the cache fills up.
+    pub fn try_new(capacity: usize) -> Result<Self, TryReserveError> {
+        let mut cache = Self::new(capacity);
+        cache.try_reserve(capacity)?;
+        Ok(cache)
+    }
+}
+impl<K: Hash + Eq + Clone, V, const C: usize> FbrCache<K, V, C> {
+    /// Create a new cache with the given capacity and aging threshold.
+    ///
+    /// The threshold is given per slot, i.e. aging happens when the average usage count
+    /// exceeds it. The product with the capacity saturates at `usize::MAX`, see
+    /// [`Self::with_absolute_age_threshold`] for specifying it directly.
+    pub fn with_age_threshold(capacity: usize, age_threshold: usize) -> Self {
+        Self::with_absolute_age_threshold(capacity, capacity.saturating_mul(age_threshold))
+    }
+
+    /// Create a new cache with the given capacity and absolute aging threshold.
+    ///
+    /// Aging happens when the sum of all usage counts exceeds `age_threshold`.
+    pub fn with_absolute_age_threshold(capacity: usize, age_threshold: usize) -> Self {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::VALID_C;
+        Self {
+            hash: Default::default(),
+            lru: Default::default(),
+            chains: [(); C].map(|_| Default::default()),
+            overflow: Default::default(),
+            occupied: Occupancy::new(C),
+            mid: 0,
+            mid_boundary: None,
+            old: 0,
+            old_boundary: None,
+            total_count: Default::default(),
+            aged_total: 0,
+            age_events: 0,
+            evictions: 0,
+            inserts: 0,
+            promotions: 0,
+            demotions: 0,
+            hits: 0,
+            misses: 0,
+            been_full: false,
+            capacity,
+            age_threshold,
+            max_count: usize::MAX,
+            evict_policy: EvictPolicy::default(),
+            scan_limit: usize::MAX,
+            promotion_policy: PromotionPolicy::default(),
+            overwrite_on_put: false,
+            admission: None,
+            insert_observer: None,
+            resize_observer: None,
+            growth: None,
+            slack: 0,
+            pinned: 0,
+            hit_count: 0,
+            auto_age: None,
+            miss_decay: 0,
+            miss_pressure: 0,
+            aging_shifts: [1; 3],
+            tombstones: Ghosts::new(),
+            tombstone_limit: 0,
+        }
+    }
+
+    /// Reserve room for at least `additional` more items in the key index.
+    ///
+    /// This never grows the capacity of the cache, it only avoids reallocations e.g.
+    /// during the initial filling phase; an allocation failure is returned as error.
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        self.hash.try_reserve(additional)
+    }
+
+    /// Put the given item into the cache, evicting another item if necessary.
+    ///
+    /// This is usually called after finding no cached value for a key and computing said value.
+    /// If the key is present already, this counts as a hit and the new value is dropped,
+    /// unless [`Self::set_overwrite_on_put`] is enabled.
+    ///
+    /// Returns `false` if the item was refused, either by the admission predicate (see
+    /// [`Self::set_admission`]) or because the cache is full of pinned items (see [`Self::pin`]).
+    pub fn put(&mut self, key: K, value: V) -> bool {
+        self.put_prio_n(key, value, 0)
+    }
+
+    /// Let the capacity grow up to `max_capacity` when the cache is too small for the working set.
+    ///
+    /// With this enabled, the cache remembers the keys of as many recently evicted items
+    /// as its capacity. When a key from this list is put into the full cache again, the
+    /// capacity is doubled (but not beyond `max_capacity`) instead of evicting an item,
+    /// since a cache of twice the size would probably still have held it. The aging
+    /// threshold grows proportionally. Below `max_capacity` evicted allocations are reused
+    /// as usual.
+    pub fn set_grow_on_demand(&mut self, max_capacity: usize) {
+        self.growth = Some(Growth {
+            max_capacity,
+            ghosts: Ghosts::new(),
+        });
+    }
+
+    /// Set a hard ceiling for usage counts, clamping the counts of current items.
+    ///
+    /// Counts of `C_MAX` and above share a single overflow chain, so evicting one of those
+    /// items requires scanning it. With a ceiling below `C_MAX` every item has its own
+    /// per-count chain, and the ceiling also bounds how long a formerly popular item
+    /// survives after aging.
+    pub fn set_max_count(&mut self, max_count: usize) {
+        self.max_count = max_count;
+        // relink every item least recent first, so that the chains stay in recency order
+        for cde in self.lru.iter().rev() {
+            let ptr = unsafe { UnsafeRef::from_raw(cde) };
+            let old_count = ptr.count;
+            let clamped = FbrEntry::clamp(&ptr, max_count);
+            self.total_count = self.total_count.saturating_sub(clamped);
+            switch_chain(
+                old_count,
+                ptr.count,
+                &mut self.chains,
+                &mut self.overflow,
+                &mut self.occupied,
+                &ptr,
+            );
+        }
+        self.debug_check();
+    }
+
+    /// Change the capacity, evicting items if the cache currently holds more.
+    ///
+    /// The aging threshold is scaled proportionally, and the room granted by
+    /// [`Self::set_hard_capacity`] stays the same. Pinned items are not evicted, so if
+    /// too many of them are left the capacity is only lowered to the resulting length.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.evict_n(self.len().saturating_sub(capacity));
+        self.resize(capacity.max(self.len()));
+    }
+
+    /// Put the given item into the cache only if the key is not present yet.
+    ///
+    /// In contrast to [`Self::put`] an existing item is left completely untouched, its usage
+    /// count and recency are not updated. Returns `true` if the item was inserted, which
+    /// also requires passing the admission predicate (see [`Self::set_admission`]).
+    pub fn put_if_absent(&mut self, key: K, value: V) -> bool {
+        if self.contains_key(&key) || !self.admits(&key) {
+            return false;
+        }
+        self.insert(key, value, 0);
+        true
+    }
+
+    /// Put the given item into the cache with elevated priority.
+    ///
+    /// This means that the item starts out with a usage count of one instead
+    /// of zero. For cyclic usage patterns this means that priority items will
+    /// accumulate in the “old” region since non-priority items are evicted
+    /// before them. As usual, this works best if only a small fraction of
+    /// items get priority.
+    pub fn put_prio(&mut self, key: K, value: V) -> bool {
+        self.put_prio_n(key, value, 1)
+    }
+
+    /// Put the given item into the cache with the given initial usage count.
+    ///
+    /// This generalises [`Self::put_prio`], the count is clamped to `C_MAX - 1`
+    /// so that the item remains subject to frequency-based eviction. The initial
+    /// count contributes towards the aging threshold and is halved by aging like
+    /// any other usage count.
+    pub fn put_prio_n(&mut self, key: K, value: V, initial_count: usize) -> bool {
+        if let Some(cde) = self.hit(&key) {
+            if self.overwrite_on_put {
+                *unsafe { FbrEntry::value_mut(&cde) } = value;
+            }
+            self.maybe_age();
+            return true;
+        }
+        if !self.admits(&key) {
+            return false;
+        }
+        self.insert(key, value, initial_count.min(C - 1));
+        true
+    }
+
+    /// Put the given item into the cache like [`Self::put`] and return a reference to the stored value.
+    ///
+    /// For a key already present this is the existing value, unless
+    /// [`Self::set_overwrite_on_put`] is enabled. Since a reference must be returned, the
+    /// admission predicate is not consulted, as for [`Self::get_or_default_mut`].
+    pub fn put_ref(&mut self, key: K, value: V) -> &V {
+        let cde = match self.hit(&key) {
+            Some(cde) => {
+                if self.overwrite_on_put {
+                    *unsafe { FbrEntry::value_mut(&cde) } = value;
+                }
+                self.maybe_age();
+                cde
+            }
+            None => self.insert(key, value, 0),
+        };
+        unsafe { FbrEntry::value(&cde) }
+    }
+
+    /// Put all given items into the cache with elevated priority, see [`Self::put_prio`].
+    ///
+    /// This is meant for bulk-loading a known working set that shall survive a subsequent
+    /// flood of new items; as with `put_prio` it works best for a small fraction of the capacity.
+    pub fn extend_prio(&mut self, items: impl IntoIterator<Item = (K, V)>) {
+        for (key, value) in items {
+            self.put_prio(key, value);
+        }
+    }
+
+    /// Fill the free room in the cache with values computed by `f` for the given keys, hottest first.
+    ///
+    /// Keys already present and duplicates are skipped, and so are keys refused by the
+    /// admission predicate. Once the cache is full, the remaining keys are ignored, so
+    /// `f` runs at most once per key and only for items actually inserted. The new items
+    /// are placed in front of the existing ones in the given order, with the first key
+    /// being most recently used; the regions are recomputed once at the end.
+    pub fn prepopulate<I, F>(&mut self, keys: I, mut f: F)
+    where
+        I: IntoIterator<Item = K>,
+        F: FnMut(&K) -> V,
+    {
+        let room = self.capacity.saturating_sub(self.len());
+        let mut seen = HashSet::new();
+        let mut items = Vec::new();
+        for key in keys {
+            if items.len() >= room {
+                break;
+            }
+            if self.hash.contains_key(&key) || seen.contains(&key) || !self.admits(&key) {
+                continue;
+            }
+            seen.insert(key.clone());
+            let value = f(&key);
+            items.push((key, value));
+        }
+        for (key, value) in items.into_iter().rev() {
+            if let Some(observer) = &mut self.insert_observer {
+                observer(&key, &value, false);
+            }
+            self.inserts += 1;
+            let entry = UnsafeRef::from_box(Box::new(FbrEntry::new(key.clone(), value)));
+            self.tombstones.forget(&key);
+            self.hash.insert(key, entry.clone());
+            self.lru.push_front(entry.clone());
+            match self.chains.first_mut() {
+                Some(chain) => {
+                    chain.push_front(entry);
+                    self.occupied.add(0);
+                }
+                None => self.overflow.push_front(entry),
+            }
+        }
+        self.rebuild_boundaries();
+        if self.len() >= self.capacity {
+            self.been_full = true;
+        }
+        self.debug_check();
+    }
+
+    /// Whether a new key passes the admission predicate and finds room.
+    fn admits(&self, key: &K) -> bool {
+        if self.admission.as_ref().is_some_and(|f| !f(key)) {
+            return false;
+        }
+        self.pinned == 0 || self.len() < self.hard_capacity() || self.victim().is_some()
+    }
+
+    /// Protect the item for the given key from eviction, returning `false` if it is absent.
+    ///
+    /// Pinned items are skipped when choosing eviction victims, also by [`Self::prune_to`],
+    /// [`Self::clear_except_top`] and [`Self::demote`]; explicit removals still apply. Beware of pinning too much: a
+    /// full cache of only pinned items refuses all new items in [`Self::put`] and its
+    /// variants, while methods that must return the value (like [`Self::get_or_default_mut`])
+    /// exceed the capacity until pinned items are unpinned or removed.
+    pub fn pin(&mut self, key: &K) -> bool {
+        match self.hash.get(key) {
+            Some(cde) => {
+                if !cde.pinned {
+                    FbrEntry::pin(cde, true);
+                    self.pinned += 1;
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Make the item for the given key evictable again, returning `false` if it is absent.
+    pub fn unpin(&mut self, key: &K) -> bool {
+        match self.hash.get(key) {
+            Some(cde) => {
+                if cde.pinned {
+                    FbrEntry::pin(cde, false);
+                    self.pinned -= 1;
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns `true` if the key is present and pinned, see [`Self::pin`].
+    pub fn is_pinned(&self, key: &K) -> bool {
+        self.hash.get(key).is_some_and(|cde| cde.pinned)
+    }
+
+    /// Consume this cache and build a new one with the given capacity, keeping frequency counts.
+    ///
+    /// If the new capacity is smaller than the number of items, the coldest items (lowest
+    /// usage count, least recent among equals) are dropped. The surviving items keep their
+    /// relative recency order; their regions are recomputed for the new capacity. The
+    /// aging threshold is scaled proportionally, the other settings are carried over
+    /// except for callbacks like [`Self::set_admission`].
+    pub fn rebuild_with_capacity(mut self, new_capacity: usize) -> Self {
+        let age_threshold =
+            self.age_threshold as u128 * new_capacity as u128 / self.capacity.max(1) as u128;
+        let entries = self.take_entries();
+        let mut keep = vec![true; entries.len()];
+        if entries.len() > new_capacity {
+            let mut order = (0..entries.len()).collect::<Vec<_>>();
+            order.sort_by_key(|&i| std::cmp::Reverse(entries[i].count));
+            for &i in &order[new_capacity..] {
+                keep[i] = false;
+            }
+        }
+        let mut cache = Self::with_absolute_age_threshold(
+            new_capacity,
+            age_threshold.min(usize::MAX as u128) as usize,
+        );
+        self.copy_settings(&mut cache);
+        for (entry, keep) in entries.into_iter().zip(keep).rev() {
+            if keep {
+                let FbrEntry {
+                    key, value, count, ..
+                } = *entry;
+                cache.insert(key, value, count);
+            }
+        }
+        cache
+    }
+
+    /// Retrieve the value for a given key
+    ///
+    /// This updates the usage count and recency, so it can be used to “ping” a
+    /// key in order to bring it to the front again.
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        let cde = self.lookup(key)?;
+        self.maybe_age();
+        Some(unsafe { FbrEntry::value(&cde) })
+    }
+
+    /// Retrieve the value for a given key like [`Self::get`], for copy-on-write modification.
+    ///
+    /// The value is only cloned once the returned handle is modified, see [`CowValue`].
+    pub fn get_cow(&mut self, key: &K) -> Option<CowValue<'_, V>>
+    where
+        V: Clone,
+    {
+        let cde = self.lookup(key)?;
+        self.maybe_age();
+        Some(CowValue {
+            cached: unsafe { FbrEntry::value_mut(&cde) },
+            modified: None,
+        })
+    }
+
+    /// Retrieve clones of the values for the given keys, like calling [`Self::get`] for each.
+    ///
+    /// Absent keys are simply omitted from the returned map, but still count as misses.
+    pub fn get_cloned_many(&mut self, keys: &[K]) -> HashMap<K, V>
+    where
+        V: Clone,
+    {
+        keys.iter()
+            .filter_map(|key| Some((key.clone(), self.get(key)?.clone())))
+            .collect()
+    }
+
+    /// Obtain a handle for re-accessing the item for the given key, see [`Self::get_unchecked`].
+    ///
+    /// This does not update usage count or recency.
+    pub fn handle(&self, key: &K) -> Option<EntryHandle<K, V>> {
+        self.hash.get(key).map(|cde| EntryHandle { ptr: ptr(cde) })
+    }
+
+    /// Retrieve the value for a handle like [`Self::get`], but without hashing the key.
+    ///
+    /// # Safety
+    ///
+    /// The handle must have been obtained from this cache via [`Self::handle`], and its
+    /// item must not have been taken out of the cache since (see [`EntryHandle`]).
+    pub unsafe fn get_unchecked(&mut self, handle: &EntryHandle<K, V>) -> &V {
+        let cde = UnsafeRef::from_raw(handle.ptr);
+        self.touch(&cde);
+        self.hits += 1;
+        self.maybe_age();
+        FbrEntry::value(&cde)
+    }
+
+    /// Returns `true` if the key is present, without updating usage count or recency.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.hash.contains_key(key)
+    }
+
+    /// Returns `true` if all given keys are present, without updating usage counts or recency.
+    pub fn contains_all(&self, keys: &[K]) -> bool {
+        keys.iter().all(|key| self.hash.contains_key(key))
+    }
+
+    /// Returns `true` if any of the given keys is present, without updating usage counts or recency.
+    pub fn contains_any(&self, keys: &[K]) -> bool {
+        keys.iter().any(|key| self.hash.contains_key(key))
+    }
+
+    /// The usage count of the given key, without updating it.
+    pub fn count_of(&self, key: &K) -> Option<usize> {
+        self.hash.get(key).map(|cde| cde.count)
+    }
+
+    /// Look at the value for a given key without updating usage count or recency.
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        self.hash.get(key).map(|cde| &cde.value)
+    }
+
+    /// Read the value for a given key without any side effects.
+    ///
+    /// In contrast to [`Self::get`], which counts as a cache hit and therefore moves the
+    /// item to the front and may increment its usage count, this leaves all statistics
+    /// untouched and only needs `&self`. It behaves exactly like [`Self::peek`].
+    pub fn get_quiet(&self, key: &K) -> Option<&V> {
+        self.peek(key)
+    }
+
+    /// Retrieve a mutable reference to the value for a given key, inserting the default if absent.
+    ///
+    /// On a hit this updates usage count and recency like [`Self::get`], on a miss the
+    /// default value is put into the cache like with [`Self::put`].
+    pub fn get_or_default_mut(&mut self, key: K) -> &mut V
+    where
+        V: Default,
+    {
+        let cde = match self.lookup(&key) {
+            Some(cde) => {
+                self.maybe_age();
+                cde
+            }
+            None => self.insert(key, V::default(), 0),
+        };
+        unsafe { FbrEntry::value_mut(&cde) }
+    }
+
+    /// Retrieve a mutable reference to the value for a given key, inserting the result of `f` if absent.
+    ///
+    /// Like [`Self::get_or_default_mut`], but the value for a miss is computed by `f`, which
+    /// is only called if the key is absent.
+    pub fn get_mut_or_insert_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &mut V {
+        let cde = match self.lookup(&key) {
+            Some(cde) => {
+                self.maybe_age();
+                cde
+            }
+            None => self.insert(key, f(), 0),
+        };
+        unsafe { FbrEntry::value_mut(&cde) }
+    }
+
+    /// Retrieve the value for a given key, inserting the result of `f` if absent.
+    ///
+    /// The returned flag is `true` for a hit and `false` if the value was just inserted;
+    /// bookkeeping is the same as for [`Self::get_mut_or_insert_with`].
+    pub fn get_or_insert_with_status<F: FnOnce() -> V>(&mut self, key: K, f: F) -> (&V, bool) {
+        let (cde, hit) = match self.lookup(&key) {
+            Some(cde) => {
+                self.maybe_age();
+                (cde, true)
+            }
+            None => (self.insert(key, f(), 0), false),
+        };
+        (unsafe { FbrEntry::value(&cde) }, hit)
+    }
+
+    /// Retrieve the value for a given key like [`Self::peek`], inserting the result of `f` if absent.
+    ///
+    /// A hit has no side effects at all: usage count, recency and statistics stay as they
+    /// are, so that e.g. prefetching does not influence eviction. A miss puts the new
+    /// item into the cache like [`Self::get_mut_or_insert_with`], possibly evicting
+    /// another one, but is not counted in [`Self::misses`].
+    pub fn peek_or_insert_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &V {
+        let cde = match self.hash.get(&key) {
+            Some(cde) => cde.clone(),
+            None => self.insert(key, f(), 0),
+        };
+        unsafe { FbrEntry::value(&cde) }
+    }
+
+    /// Retrieve the value for a given key, recomputing it if absent or no longer fresh.
+    ///
+    /// On a hit this acts like [`Self::get`]; if `is_fresh` rejects the cached value, it
+    /// is replaced by the result of `recompute`, keeping usage count and recency. On a
+    /// miss the recomputed value is put into the cache like with [`Self::put`].
+    pub fn get_or_recompute<P, F>(&mut self, key: K, is_fresh: P, recompute: F) -> &V
+    where
+        P: FnOnce(&V) -> bool,
+        F: FnOnce() -> V,
+    {
+        let cde = match self.lookup(&key) {
+            Some(cde) => {
+                self.maybe_age();
+                let value = unsafe { FbrEntry::value_mut(&cde) };
+                if !is_fresh(value) {
+                    *value = recompute();
+                }
+                cde
+            }
+            None => self.insert(key, recompute(), 0),
+        };
+        unsafe { FbrEntry::value(&cde) }
+    }
+
+    /// Transform the value for a given key in place, returning `false` if the key is absent.
+    ///
+    /// The value is moved into `f` and the result stored in the same allocation. Usage
+    /// count, region and recency stay untouched, as with [`Self::peek`]. If `f` panics, the
+    /// item is removed from the cache since its value is gone.
+    pub fn replace_with<F: FnOnce(V) -> V>(&mut self, key: &K, f: F) -> bool {
+        let cde = match self.hash.get(key) {
+            Some(cde) => cde.clone(),
+            None => return false,
+        };
+
+        /// Removes the item without dropping its value when `f` unwinds.
+        struct Guard<'a, K: Hash + Eq + Clone, V, const C: usize> {
+            cache: &'a mut FbrCache<K, V, C>,
+            cde: UnsafeRef<FbrEntry<K, V>>,
+        }
+        impl<K: Hash + Eq + Clone, V, const C: usize> Drop for Guard<'_, K, V, C> {
+            fn drop(&mut self) {
+                self.cache.unlink(&self.cde);
+                let FbrEntry { value, count, .. } =
+                    *unsafe { UnsafeRef::into_box(self.cde.clone()) };
+                std::mem::forget(value);
+                self.cache.total_count = self.cache.total_count.saturating_sub(count);
+            }
+        }
+
+        let slot: *mut V = unsafe { FbrEntry::value_mut(&cde) };
+        let guard = Guard { cache: self, cde };
+        unsafe { slot.write(f(slot.read())) };
+        std::mem::forget(guard);
+        true
+    }
+
+    /// Replace the value for a given key, returning the old value, or the new one if the key is absent.
+    ///
+    /// Usage count, region and recency stay untouched, as with [`Self::replace_with`].
+    pub fn set_value(&mut self, key: &K, value: V) -> Result<V, V> {
+        match self.hash.get(key) {
+            Some(cde) => Ok(std::mem::replace(
+                unsafe { FbrEntry::value_mut(cde) },
+                value,
+            )),
+            None => Err(value),
+        }
+    }
+
+    /// Update usage count and recency for all given keys that are present, returning their number.
+    ///
+    /// This has the same effect as calling [`Self::get`] for each key in reverse order, so
+    /// afterwards the touched keys are at the front in the order given by the slice. Aging
+    /// is checked only once at the end.
+    pub fn touch_many(&mut self, keys: &[K]) -> usize {
+        let touched = keys
+            .iter()
+            .rev()
+            .filter(|key| self.hit(key).is_some())
+            .count();
+        self.maybe_age();
+        touched
+    }
+
+    /// Count a use of the given key like [`Self::get`], plus one extra increment of its usage count.
+    ///
+    /// This is meant for an access known to be important, similar to putting an item with
+    /// [`Self::put_prio`]: the extra increment applies even in the “new” region, where a
+    /// normal hit leaves the count unchanged, and respects [`Self::max_count`]. The item
+    /// always becomes the most recently used one, also under [`PromotionPolicy::NotInNew`].
+    /// Returns `false` if the key is absent, which counts as a miss.
+    pub fn touch_prio(&mut self, key: &K) -> bool {
+        let Some(cde) = self.lookup(key) else {
+            return false;
+        };
+        if cde.region == Region::New && self.promotion_policy == PromotionPolicy::NotInNew {
+            // the hit left the item in place, but its chain needs it to be the most recent;
+            // no boundary points into the “new” region, so none needs adjusting
+            unsafe { self.lru.cursor_mut_from_ptr(cde.as_ref()) }.remove();
+            self.lru.push_front(cde.clone());
+        }
+        let old_count = cde.count;
+        FbrEntry::bump(&cde, 1, self.max_count);
+        switch_chain(
+            old_count,
+            cde.count,
+            &mut self.chains,
+            &mut self.overflow,
+            &mut self.occupied,
+            &cde,
+        );
+        self.total_count += cde.count - old_count;
+        self.maybe_age();
+        self.debug_check();
+        true
+    }
+
+    /// Get the entry for the given key for in-place manipulation.
+    ///
+    /// If the key is present, this counts as a cache hit like [`Self::get`].
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, C> {
+        match self.lookup(&key) {
+            Some(entry) => {
+                self.maybe_age();
+                Entry::Occupied(OccupiedEntry { cache: self, entry })
+            }
+            None => Entry::Vacant(VacantEntry { cache: self, key }),
+        }
+    }
+
+    /// Like [`Self::entry`], but only borrowing the key for the lookup.
+    ///
+    /// An owned key is only constructed when inserting into a vacant entry, which makes
+    /// this preferable for keys that are expensive to clone.
+    pub fn entry_ref<'a, 'q, Q>(&'a mut self, key: &'q Q) -> EntryRef<'a, 'q, K, Q, V, C>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.lookup(key) {
+            Some(entry) => {
+                self.maybe_age();
+                EntryRef::Occupied(OccupiedEntry { cache: self, entry })
+            }
+            None => EntryRef::Vacant(VacantEntryRef { cache: self, key }),
+        }
+    }
+
+    /// Like [`Self::hit`], but counting the lookup in the hit/miss statistics.
+    fn lookup<Q>(&mut self, key: &Q) -> Option<UnsafeRef<FbrEntry<K, V>>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let found = self.hit(key);
+        if found.is_some() {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+            if self.miss_decay > 0 {
+                self.miss_pressure = self.miss_pressure.saturating_add(self.miss_decay);
+                self.maybe_age();
+            }
+        }
+        found
+    }
+
+    /// Update usage count and recency for the given key, without periodic aging.
+    fn hit<Q>(&mut self, key: &Q) -> Option<UnsafeRef<FbrEntry<K, V>>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let cde = self.hash.get(key)?.clone();
+        self.touch(&cde);
+        self.debug_check();
+        Some(cde)
+    }
+
+    /// Update usage count and recency for the given item, see [`Self::hit`].
+    fn touch(&mut self, cde: &UnsafeRef<FbrEntry<K, V>>) {
+        self.hit_count += 1;
+        let region = cde.region;
+        if region == Region::New && self.promotion_policy == PromotionPolicy::NotInNew {
+            // neither count nor position change, so the count chain must stay as it is
+            return;
+        }
+        let old_count = FbrEntry::access(cde, self.max_count);
+        let new_count = cde.count;
+        switch_chain(
+            old_count,
+            new_count,
+            &mut self.chains,
+            &mut self.overflow,
+            &mut self.occupied,
+            cde,
+        );
+        self.total_count += new_count - old_count;
+        unsafe {
+            let mut cursor = self.lru.cursor_mut_from_ptr(cde.as_ref());
+            if optr(&self.mid_boundary) == ptr(cde) {
+                self.mid_boundary = cursor.peek_next().clone_pointer();
+            }
+            if optr(&self.old_boundary) == ptr(cde) {
+                self.old_boundary = cursor.peek_next().clone_pointer();
+            }
+            cursor.remove();
+        };
+        self.lru.push_front(cde.clone());
+        if region > Region::New {
+            self.promotions += 1;
+        }
+        self.demotions += move_boundaries(
+            region,
+            self.len(),
+            self.mid,
+            self.old,
+            &self.lru,
+            &mut self.mid_boundary,
+            &mut self.old_boundary,
+        );
+    }
+
+    /// Periodic aging: halve all usage counts once their sum exceeds the threshold.
+    fn maybe_age(&mut self) {
+        if self.total_count.saturating_add(self.miss_pressure) > self.age_threshold {
+            let increments = self.accesses_since_age();
+            let mut shifts = self.aging_shifts;
+            for pass in 0..2 {
+                // least recent first, so that the chains stay in recency order
+                for cde in self.lru.iter().rev() {
+                    let ptr = unsafe { UnsafeRef::from_raw(cde) };
+                    let old_count = ptr.count;
+                    let aged = FbrEntry::age(&ptr, shifts[ptr.region as usize]);
+                    self.total_count = self.total_count.saturating_sub(aged);
+                    switch_chain(
+                        old_count,
+                        ptr.count,
+                        &mut self.chains,
+                        &mut self.overflow,
+                        &mut self.occupied,
+                        &ptr,
+                    );
+                }
+                if pass == 1 || self.total_count <= self.age_threshold {
+                    break;
+                }
+                // halve the spared regions after all
+                shifts = shifts.map(|shift| u32::from(shift == 0));
+                if shifts == [0; 3] {
+                    break;
+                }
+            }
+            self.aged_total = self.total_count;
+            self.miss_pressure = 0;
+            self.age_events += 1;
+            if let Some(auto) = &mut self.auto_age {
+                // leave room for as many increments as expected during the next interval
+                let observed = (self.hit_count - auto.since).max(1);
+                let headroom = increments as f64 * auto.interval as f64 / observed as f64;
+                self.age_threshold = self.total_count.saturating_add((headroom as usize).max(1));
+                auto.since = self.hit_count;
+            }
+            self.debug_check();
+        }
+    }
+
+    /// The region the given key currently lives in, without touching its statistics.
+    pub fn region_of(&self, key: &K) -> Option<Region> {
+        self.hash.get(key).map(|cde| cde.region)
+    }
+
+    /// Returns `true` if the key is present and currently lives in the given region.
+    ///
+    /// Like [`Self::region_of`] this does not touch usage count or recency.
+    pub fn contains_in_region(&self, key: &K, region: Region) -> bool {
+        self.region_of(key) == Some(region)
+    }
+
+    /// An iterator like [`Self::iter`] that starts at the given key.
+    ///
+    /// This yields the given key and all less recently used items, which allows resuming
+    /// a previous iteration. If the key is not present, the iterator is empty.
+    pub fn iter_from(&self, key: &K) -> impl Iterator<Item = (&K, &V, usize, Region)> {
+        self.entries_from(self.hash.get(key).map(|cde| cde.as_ref()))
+            .map(|cde| (&cde.key, &cde.value, cde.count, cde.region))
+    }
+
+    /// The position of the given key in recency order, 0 being the most recently used.
+    ///
+    /// This walks the recency list and therefore takes time linear in the rank.
+    pub fn recency_rank(&self, key: &K) -> Option<usize> {
+        let target = ptr(self.hash.get(key)?);
+        self.lru.iter().position(|cde| std::ptr::eq(cde, target))
+    }
+
+    /// Returns `true` if the key is present and safe from the next eviction.
+    ///
+    /// The item that the [`EvictPolicy`] would evict next is not protected: with the
+    /// default policy that is the least recent among the lowest-count items in the “old”
+    /// region, or the least recently used item if the “old” region is empty. All other
+    /// items are protected against a single eviction, i.e. putting a new item into a full
+    /// cache will not evict them unless a hard capacity is set (see
+    /// [`Self::set_hard_capacity`]): reaching it evicts a whole batch, whose items are
+    /// the first ones listed by [`Self::iter_eviction_order`].
+    pub fn is_protected(&self, key: &K) -> bool {
+        match self.hash.get(key) {
+            Some(cde) => optr(&self.victim()) != ptr(cde),
+            None => false,
+        }
+    }
+
+    /// Run [`Self::check_invariants`] after a mutation in this crate's tests or with the
+    /// `check-invariants` feature; this takes linear time, so it is off otherwise.
+    #[inline]
+    fn debug_check(&self) {
+        #[cfg(any(test, feature = "check-invariants"))]
+        assert_eq!(self.check_invariants(), Ok(()));
+    }
+
+    /// Verify the consistency of the internal data structures.
+    ///
+    /// This checks that hash map and recency list agree, that each item is linked into
+    /// the count chain matching its usage count in recency order, and that regions and
+    /// their boundaries match the item positions. It takes time linear in the number of
+    /// items and is meant for tests and debugging.
+    pub fn check_invariants(&self) -> Result<(), InvariantError> {
+        let len = self.lru.iter().count();
+        if len != self.hash.len() {
+            return Err(InvariantError::LengthMismatch {
+                hash: self.hash.len(),
+                lru: len,
+            });
+        }
+        for (position, cde) in self.lru.iter().enumerate() {
+            if self.hash.get(&cde.key).map(ptr) != Some(cde as *const _) {
+                return Err(InvariantError::HashMismatch { position });
+            }
+            let expected = if position < self.mid {
+                Region::New
+            } else if position < self.old {
+                Region::Middle
+            } else {
+                Region::Old
+            };
+            if cde.region != expected {
+                return Err(InvariantError::RegionMismatch {
+                    position,
+                    expected,
+                    found: cde.region,
+                });
+            }
+        }
+        let mut chains = 0;
+        for (chain, list) in self.chains.iter().enumerate() {
+            if self.occupied.len(chain) != list.iter().count() {
+                return Err(InvariantError::OccupancyMismatch { chain });
+            }
+            for cde in list.iter() {
+                if cde.count != chain {
+                    return Err(InvariantError::ChainMismatch {
+                        chain,
+                        count: cde.count,
+                    });
+                }
+                chains += 1;
+            }
+        }
+        for cde in self.overflow.iter() {
+            if cde.count < C {
+                return Err(InvariantError::ChainMismatch {
+                    chain: C,
+                    count: cde.count,
+                });
+            }
+            chains += 1;
+        }
+        if chains != len {
+            return Err(InvariantError::ChainLength {
+                chains,
+                expected: len,
+            });
+        }
+        // each chain must list its items in the same order as the recency list
+        let mut cursors = self
+            .chains
+            .iter()
+            .map(|list| list.iter())
+            .collect::<Vec<_>>();
+        let mut overflow = self.overflow.iter();
+        for (position, cde) in self.lru.iter().enumerate() {
+            let next = match cursors.get_mut(cde.count) {
+                Some(cursor) => cursor.next(),
+                None => overflow.next(),
+            };
+            if !next.is_some_and(|next| std::ptr::eq(next, cde)) {
+                return Err(InvariantError::ChainOrder { position });
+            }
+        }
+        let at = |idx: usize| self.lru.iter().nth(idx).map(|cde| cde as *const _);
+        if self.mid != len * 3 / 10 || optr(&self.mid_boundary) != at(self.mid).unwrap_or(null()) {
+            return Err(InvariantError::BoundaryMismatch(Region::Middle));
+        }
+        if self.old != len * 3 / 4 || optr(&self.old_boundary) != at(self.old).unwrap_or(null()) {
+            return Err(InvariantError::BoundaryMismatch(Region::Old));
+        }
+        Ok(())
+    }
+
+    /// Remove the item for the given key, returning its value.
+    ///
+    /// The items behind it in recency order move up, so that items may change their region.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.remove_if(key, |_, _| true)
+    }
+
+    /// Remove the item for the given key only if the predicate returns `true` for it.
+    ///
+    /// Otherwise the item is left untouched and `None` is returned. The predicate is
+    /// not called if the key is not present.
+    pub fn remove_if<F: FnOnce(&K, &V) -> bool>(&mut self, key: &K, pred: F) -> Option<V> {
+        let cde = self.hash.get(key)?.clone();
+        if !pred(&cde.key, &cde.value) {
+            return None;
+        }
+        Some(self.remove_entry(cde))
+    }
+
+    /// Remove the item for the given key like [`Self::remove`], leaving a tombstone if enabled.
+    ///
+    /// With tombstones enabled via [`Self::set_tombstones`], [`Self::status`] reports the
+    /// key as recently removed until it is put into the cache again or forgotten.
+    pub fn take(&mut self, key: &K) -> Option<V> {
+        let value = self.remove(key)?;
+        if self.tombstone_limit > 0 {
+            self.tombstones.remember(key, self.tombstone_limit);
+        }
+        Some(value)
+    }
+
+    /// Remember up to `limit` keys removed by [`Self::take`], forgetting the oldest first.
+    ///
+    /// The default of zero disables tombstones, and lowering the limit forgets the oldest
+    /// keys beyond it right away.
+    pub fn set_tombstones(&mut self, limit: usize) {
+        self.tombstone_limit = limit;
+        self.tombstones.truncate(limit);
+    }
+
+    /// Whether the given key is cached or was recently taken, without any side effects.
+    pub fn status(&self, key: &K) -> EntryStatus {
+        if self.hash.contains_key(key) {
+            EntryStatus::Present
+        } else if self.tombstones.contains(key) {
+            EntryStatus::RecentlyRemoved
+        } else {
+            EntryStatus::Absent
+        }
+    }
+
+    fn remove_entry(&mut self, cde: UnsafeRef<FbrEntry<K, V>>) -> V {
+        self.unlink(&cde);
+        let entry = unsafe { UnsafeRef::into_box(cde) };
+        self.total_count = self.total_count.saturating_sub(entry.count);
+        self.debug_check();
+        entry.value
+    }
+
+    /// Move the item for the given key into `dest`, keeping its usage count.
+    ///
+    /// The item becomes the most recently used one in `dest`, evicting another item there
+    /// if necessary, but bypassing its admission predicate. Returns `false` and changes
+    /// nothing if the key is absent here or already present in `dest`.
+    pub fn rehome(&mut self, key: &K, dest: &mut Self) -> bool {
+        if dest.hash.contains_key(key) {
+            return false;
+        }
+        let Some(cde) = self.hash.get(key).cloned() else {
+            return false;
+        };
+        self.unlink(&cde);
+        let FbrEntry {
+            key, value, count, ..
+        } = *unsafe { UnsafeRef::into_box(cde) };
+        self.total_count = self.total_count.saturating_sub(count);
+        let count = count.min(dest.max_count);
+        dest.insert(key, value, count);
+        true
+    }
+
+    /// Merge all items of `other` into this cache, combining the values of shared keys.
+    ///
+    /// For a key held by both caches `resolve(key, mine, theirs)` computes the new value,
+    /// the usage counts are summed (capped at [`Self::max_count`]) and the item becomes
+    /// most recently used, keeping its pin from this cache; such a replacement is neither
+    /// announced to [`Self::on_insert`] nor counted in [`Self::inserts`]. All other items
+    /// of `other` are put into this cache with their usage counts, subject to admission and
+    /// evicting items as necessary; they are not pinned here. Items of `other` are processed
+    /// from least to most recently used, so its most recent item ends up at the front.
+    pub fn merge_with<F: FnMut(&K, V, V) -> V>(&mut self, mut other: Self, mut resolve: F) {
+        for entry in other.take_entries().into_iter().rev() {
+            let FbrEntry {
+                key, value, count, ..
+            } = *entry;
+            let count = count.min(self.max_count);
+            match self.hash.get(&key).cloned() {
+                Some(cde) => {
+                    let pinned = cde.pinned;
+                    self.unlink(&cde);
+                    let mine = *unsafe { UnsafeRef::into_box(cde) };
+                    self.total_count = self.total_count.saturating_sub(mine.count);
+                    let value = resolve(&key, mine.value, value);
+                    let count = mine.count.saturating_add(count).min(self.max_count);
+                    // a replacement rather than an insertion: no room to make, nothing to announce
+                    let entry = UnsafeRef::from_box(Box::new(FbrEntry::new(key.clone(), value)));
+                    let cde = self.link(key, entry, count);
+                    if pinned {
+                        FbrEntry::pin(&cde, true);
+                        self.pinned += 1;
+                    }
+                }
+                None if self.admits(&key) => {
+                    self.insert(key, value, count);
+                }
+                None => {}
+            }
+        }
+        self.maybe_age();
+        self.debug_check();
+    }
+
+    /// Remove all items of the given region, returning them in recency order.
+    ///
+    /// Each item is returned together with its usage count. The remaining items are
+    /// shifted into the vacated region, like after [`Self::remove`].
+    pub fn drain_region(&mut self, region: Region) -> Vec<(K, V, usize)> {
+        let entries = self
+            .region_iter(region)
+            .map(|cde| unsafe { UnsafeRef::from_raw(cde) })
+            .collect::<Vec<_>>();
+        let mut drained = Vec::with_capacity(entries.len());
+        for cde in entries {
+            self.unlink(&cde);
+            let FbrEntry {
+                key, value, count, ..
+            } = *unsafe { UnsafeRef::into_box(cde) };
+            self.total_count = self.total_count.saturating_sub(count);
+            drained.push((key, value, count));
+        }
+        drained
+    }
+
+    /// Remove all items that are not in one of the given regions.
+    ///
+    /// Region membership is determined once before removing anything; afterwards the
+    /// remaining items are divided into regions anew, like after [`Self::drain_region`].
+    pub fn retain_regions(&mut self, keep: &[Region]) {
+        let entries = self
+            .lru
+            .iter()
+            .filter(|cde| !keep.contains(&cde.region))
+            .map(|cde| unsafe { UnsafeRef::from_raw(cde) })
+            .collect::<Vec<_>>();
+        for cde in entries {
+            self.unlink(&cde);
+            self.total_count = self.total_count.saturating_sub(cde.count);
+            unsafe { UnsafeRef::into_box(cde) };
+        }
+    }
+
+    /// Keep only the items for which `f` returns `true`, allowing it to modify their values.
+    ///
+    /// `f` sees all items once, most recently used first, without changing usage counts or
+    /// recency. The rejected items are removed after the scan, like with [`Self::remove`],
+    /// so the remaining ones are divided into regions anew.
+    pub fn retain_mut<F: FnMut(&K, &mut V) -> bool>(&mut self, mut f: F) {
+        let entries = self
+            .lru
+            .iter()
+            .map(|cde| unsafe { UnsafeRef::from_raw(cde) })
+            .filter(|cde| !f(&cde.key, unsafe { FbrEntry::value_mut(cde) }))
+            .collect::<Vec<_>>();
+        for cde in entries {
+            self.remove_entry(cde);
+        }
+    }
+
+    /// Move all items of the given region into a new cache, keeping their usage counts.
+    ///
+    /// The new cache holds exactly these items at full capacity (but at least 4) and
+    /// inherits the configuration of this one, with the aging threshold scaled to its
+    /// capacity. Recency order is preserved, while the regions are recomputed for the
+    /// smaller size. This cache is left as after [`Self::drain_region`].
+    pub fn split_off_region(&mut self, region: Region) -> Self {
+        let entries = self.drain_region(region);
+        self.split_off(entries)
+    }
+
+    /// Move all items from the given recency rank on into a new cache, keeping their usage counts.
+    ///
+    /// This cache keeps its `rank` most recently used items, the rest is sized and
+    /// configured like with [`Self::split_off_region`]. The regions of both caches are
+    /// recomputed from their recency order.
+    pub fn split_at_rank(&mut self, rank: usize) -> Self {
+        let entries = self
+            .lru
+            .iter()
+            .skip(rank)
+            .map(|cde| unsafe { UnsafeRef::from_raw(cde) })
+            .collect::<Vec<_>>();
+        let mut moved = Vec::with_capacity(entries.len());
+        for cde in entries {
+            self.unlink(&cde);
+            let FbrEntry {
+                key, value, count, ..
+            } = *unsafe { UnsafeRef::into_box(cde) };
+            self.total_count = self.total_count.saturating_sub(count);
+            moved.push((key, value, count));
+        }
+        self.split_off(moved)
+    }
+
+    /// Build a cache for items removed from this one, given most recently used first.
+    fn split_off(&self, entries: Vec<(K, V, usize)>) -> Self {
+        let capacity = entries.len().max(4);
+        let age_threshold =
+            self.age_threshold as u128 * capacity as u128 / self.capacity.max(1) as u128;
+        let mut cache = Self::with_absolute_age_threshold(
+            capacity,
+            age_threshold.min(usize::MAX as u128) as usize,
+        );
+        self.copy_settings(&mut cache);
+        for (key, value, count) in entries.into_iter().rev() {
+            cache.insert(key, value, count);
+        }
+        cache
+    }
+
+    /// Carry all settings over to a derived cache, except capacity, aging threshold and callbacks.
+    ///
+    /// Learned state like ghost keys, tombstones and the measured aging cadence is not copied.
+    fn copy_settings<W>(&self, cache: &mut FbrCache<K, W, C>) {
+        cache.max_count = self.max_count;
+        cache.evict_policy = self.evict_policy;
+        cache.scan_limit = self.scan_limit;
+        cache.promotion_policy = self.promotion_policy;
+        cache.overwrite_on_put = self.overwrite_on_put;
+        cache.slack = self.slack;
+        cache.growth = self.growth.as_ref().map(|growth| Growth {
+            max_capacity: growth.max_capacity,
+            ghosts: Ghosts::new(),
+        });
+        cache.auto_age = self.auto_age.as_ref().map(|auto| AutoAge {
+            interval: auto.interval,
+            since: cache.hit_count,
+        });
+        cache.miss_decay = self.miss_decay;
+        cache.aging_shifts = self.aging_shifts;
+        cache.tombstone_limit = self.tombstone_limit;
+    }
+
+    /// Build a cache holding the same keys with values transformed by `f`.
+    ///
+    /// Usage counts, recency order, regions and pinning are the same as in this cache,
+    /// and so is the configuration except for callbacks like [`Self::set_admission`].
+    /// `f` is called for the items from least to most recently used.
+    pub fn map_values<W, F: FnMut(&V) -> W>(&self, mut f: F) -> FbrCache<K, W, C> {
+        let mut cache = FbrCache::with_absolute_age_threshold(self.capacity, self.age_threshold);
+        self.copy_settings(&mut cache);
+        for cde in self.lru.iter().rev() {
+            let entry = cache.insert(cde.key.clone(), f(&cde.value), cde.count);
+            if cde.pinned {
+                FbrEntry::pin(&entry, true);
+                cache.pinned += 1;
+            }
+        }
+        cache.total_count = self.total_count;
+        cache.aged_total = self.aged_total;
+        cache
+    }
+
+    /// Report which item the next eviction would choose and why, without changing anything.
+    ///
+    /// This is a diagnostic for debug builds only; it returns `None` if the cache is empty
+    /// or all items are pinned.
+    #[cfg(debug_assertions)]
+    pub fn explain_eviction(&self) -> Option<EvictExplanation<K>> {
+        let (cde, reason) = self.victim_with_reason()?;
+        Some(EvictExplanation {
+            key: cde.key.clone(),
+            count: cde.count,
+            region: cde.region,
+            reason,
+        })
+    }
+
+    /// All items in the order in which repeated evictions would remove them, coldest first.
+    ///
+    /// This is the order of [`Self::demote`], taking into account that region boundaries
+    /// move as items are removed, which makes it suitable for flushing dirty items in
+    /// order of their eviction risk. Pinned items come last, least recently used first.
+    /// The order is computed up front on a copy of the keys and counts, so this takes time
+    /// and memory proportional to the number of items.
+    pub fn iter_eviction_order(&self) -> impl Iterator<Item = (&K, &V, usize)> {
+        let mut shadow = self.map_values(|_| ());
+        let evictable = shadow.demote(shadow.len());
+        let evictable = evictable.into_iter().map(move |(key, _, _)| {
+            let (key, cde) = self.hash.get_key_value(&key).expect("shadow key missing");
+            (key, &cde.value, cde.count)
+        });
+        let pinned = self.lru.iter().rev().filter(|cde| cde.pinned);
+        evictable.chain(pinned.map(|cde| (&cde.key, &cde.value, cde.count)))
+    }
+
+    /// Remove the `min_items` coldest items, returning them with their usage counts.
+    ///
+    /// Items are chosen in the order in which they would be evicted, which makes this
+    /// suitable for moving cold items to a lower storage tier. Like [`Self::drain_region`]
+    /// this is an explicit removal, not counted in [`Self::evictions`].
+    pub fn demote(&mut self, min_items: usize) -> Vec<(K, V, usize)> {
+        let mut demoted = Vec::with_capacity(min_items.min(self.len()));
+        while demoted.len() < min_items {
+            match self.take_victim() {
+                Some(item) => demoted.push(item),
+                None => break,
+            }
+        }
+        demoted
+    }
+
+    /// Like [`Self::demote`], but remove cold items until their total weight reaches `min_weight`.
+    pub fn demote_weighted<F>(&mut self, min_weight: usize, mut weight: F) -> Vec<(K, V, usize)>
+    where
+        F: FnMut(&K, &V) -> usize,
+    {
+        let mut demoted = Vec::new();
+        let mut total = 0usize;
+        while total < min_weight {
+            match self.take_victim() {
+                Some(item) => {
+                    total = total.saturating_add(weight(&item.0, &item.1));
+                    demoted.push(item);
+                }
+                None => break,
+            }
+        }
+        demoted
+    }
+
+    fn take_victim(&mut self) -> Option<(K, V, usize)> {
+        let cde = self.victim()?;
+        self.unlink(&cde);
+        let FbrEntry {
+            key, value, count, ..
+        } = *unsafe { UnsafeRef::into_box(cde) };
+        self.total_count = self.total_count.saturating_sub(count);
+        self.debug_check();
+        Some((key, value, count))
+    }
+
+    fn insert(&mut self, key: K, value: V, count: usize) -> UnsafeRef<FbrEntry<K, V>> {
+        let entry = match self.make_room(&key, &value, count) {
+            Some(e) => {
+                FbrEntry::reuse(&e, key.clone(), value);
+                e
+            }
+            // only pinned items left when full
+            None => UnsafeRef::from_box(Box::new(FbrEntry::new(key.clone(), value))),
+        };
+        let entry = self.link(key, entry, count);
+        self.debug_check();
+        entry
+    }
+
+    /// Like `insert`, but returning the item evicted to make room instead of reusing its allocation.
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn insert_evicting(
+        &mut self,
+        key: K,
+        value: V,
+        count: usize,
+    ) -> (UnsafeRef<FbrEntry<K, V>>, Option<(K, V)>) {
+        let evicted = self.make_room(&key, &value, count).map(|cde| {
+            let FbrEntry { key, value, .. } = *unsafe { UnsafeRef::into_box(cde) };
+            (key, value)
+        });
+        let entry = UnsafeRef::from_box(Box::new(FbrEntry::new(key.clone(), value)));
+        let entry = self.link(key, entry, count);
+        self.debug_check();
+        (entry, evicted)
+    }
+
+    /// Announce a new item, then grow or evict as needed, returning the last evicted item.
+    fn make_room(&mut self, key: &K, value: &V, count: usize) -> Option<UnsafeRef<FbrEntry<K, V>>> {
+        if let Some(observer) = &mut self.insert_observer {
+            observer(key, value, count > 0);
+        }
+        self.inserts += 1;
+        if self.len() >= self.capacity {
+            if let Some(growth) = &self.growth {
+                if self.capacity < growth.max_capacity && growth.ghosts.contains(key) {
+                    let capacity = self.capacity.saturating_mul(2).min(growth.max_capacity);
+                    self.resize(capacity);
+                }
+            }
+        }
+        if self.len() >= self.hard_capacity() {
+            // after overshooting go back to the capacity in one batch
+            self.evict_n(self.len() - self.capacity);
+            self.evict()
+        } else {
+            None
+        }
+    }
+
+    /// Link a fresh entry for the given key into all data structures.
+    fn link(
+        &mut self,
+        key: K,
+        entry: UnsafeRef<FbrEntry<K, V>>,
+        count: usize,
+    ) -> UnsafeRef<FbrEntry<K, V>> {
+        FbrEntry::bump(&entry, count, self.max_count);
+        self.total_count = self.total_count.saturating_add(entry.count);
+        self.tombstones.forget(&key);
+        self.hash.insert(key, entry.clone());
+        self.lru.push_front(entry.clone());
+        self.demotions += move_boundaries(
+            Region::Old,
+            self.len(),
+            self.mid,
+            self.old,
+            &self.lru,
+            &mut self.mid_boundary,
+            &mut self.old_boundary,
+        );
+        self.rebalance();
+        if entry.count < C {
+            self.chains[entry.count].push_front(entry.clone());
+            self.occupied.add(entry.count);
+        } else {
+            self.overflow.push_front(entry.clone());
+        }
+        if self.len() == self.capacity {
+            self.been_full = true;
+        }
+        entry
+    }
+
+    /// Evict items until at most `target_len` remain, returning the number of evicted items.
+    ///
+    /// Items are chosen by the same frequency-based policy as when making room for a
+    /// [`Self::put`]. In contrast to changing the capacity this is a one-off operation:
+    /// the cache will subsequently fill up to its configured capacity again.
+    pub fn prune_to(&mut self, target_len: usize) -> usize {
+        self.evict_n(self.len().saturating_sub(target_len))
+    }
+
+    /// Evict all but the `keep` hottest items, returning the number of evicted items.
+    ///
+    /// In contrast to [`Self::prune_to`], which follows the eviction policy and thus
+    /// tends to keep recent items, this keeps the items with the highest usage counts
+    /// (the more recent ones among equals, like [`Self::hot_keys`]), regardless of their
+    /// region. Like other evictions this skips pinned items, which are kept in addition
+    /// to the `keep` hottest unpinned ones. It is meant for carrying warm state over into a
+    /// new workload phase, hence the aging sum is reset to the usage counts of the
+    /// surviving items.
+    pub fn clear_except_top(&mut self, keep: usize) -> usize {
+        let mut entries = self
+            .lru
+            .iter()
+            .filter(|cde| !cde.pinned)
+            .map(|cde| unsafe { UnsafeRef::from_raw(cde) })
+            .collect::<Vec<_>>();
+        if entries.len() <= keep {
+            return 0;
+        }
+        entries.sort_by_key(|cde| std::cmp::Reverse(cde.count));
+        let evicted = entries.len() - keep;
+        for cde in entries.drain(keep..) {
+            self.unlink(&cde);
+            self.evictions += 1;
+            unsafe { UnsafeRef::into_box(cde) };
+        }
+        self.total_count = self.lru.iter().map(|cde| cde.count).sum();
+        self.aged_total = self.aged_total.min(self.total_count);
+        self.debug_check();
+        evicted
+    }
+
+    /// Set all usage counts to zero while keeping the items, restarting frequency learning.
+    ///
+    /// Recency order is kept, and so are the regions since they only depend on the
+    /// position of an item within this order.
+    pub fn reset_counts(&mut self) {
+        // least recent first, so that the chains stay in recency order
+        for cde in self.lru.iter().rev() {
+            let ptr = unsafe { UnsafeRef::from_raw(cde) };
+            let old_count = ptr.count;
+            FbrEntry::clamp(&ptr, 0);
+            switch_chain(
+                old_count,
+                0,
+                &mut self.chains,
+                &mut self.overflow,
+                &mut self.occupied,
+                &ptr,
+            );
+        }
+        self.total_count = 0;
+        self.aged_total = 0;
+        self.debug_check();
+    }
+
+    /// Evict and drop up to `n` items, returning the number of evicted items.
+    fn evict_n(&mut self, n: usize) -> usize {
+        let mut evicted = 0;
+        while evicted < n {
+            match self.evict() {
+                Some(cde) => unsafe { UnsafeRef::into_box(cde) },
+                None => break,
+            };
+            evicted += 1;
+        }
+        evicted
+    }
+
+    /// The item to evict next, `None` if all items are pinned.
+    fn victim(&self) -> Option<UnsafeRef<FbrEntry<K, V>>> {
+        self.victim_with_reason().map(|(cde, _)| cde)
+    }
+
+    fn victim_with_reason(&self) -> Option<(UnsafeRef<FbrEntry<K, V>>, EvictReason)> {
+        let old = |cde: &&FbrEntry<K, V>| cde.region == Region::Old;
+        let found = match self.evict_policy {
+            // chains are in recency order, so their “old” items are at the back
+            EvictPolicy::LowestCount => {
+                let mut scanned = 0;
+                occupied_chains(&self.occupied)
+                    .take(self.scan_limit)
+                    .find_map(|idx| {
+                        scanned += 1;
+                        let chain = &self.chains[idx];
+                        let cde = chain.iter().rev().take_while(old).find(|cde| !cde.pinned)?;
+                        Some((cde, EvictReason::OldChain(idx)))
+                    })
+                    .or_else(|| {
+                        if scanned == self.scan_limit {
+                            return None;
+                        }
+                        // only items with counts of C_MAX and above left in the old region
+                        self.overflow
+                            .iter()
+                            .rev()
+                            .filter(|cde| cde.region == Region::Old && !cde.pinned)
+                            .min_by_key(|cde| cde.count)
+                            .map(|cde| (cde, EvictReason::OldOverflow))
+                    })
+            }
+            EvictPolicy::LruBelow(limit) => self
+                .lru
+                .iter()
+                .rev()
+                .take_while(old)
+                .find(|cde| cde.count < limit && !cde.pinned)
+                .map(|cde| (cde, EvictReason::OldBelowLimit)),
+            EvictPolicy::Lru => None,
+        };
+        // in case old region didn’t contain a suitable candidate, evict LRU
+        found
+            .or_else(|| {
+                let cde = self.lru.iter().rev().find(|cde| !cde.pinned)?;
+                Some((cde, EvictReason::LruFallback))
+            })
+            .map(|(cde, reason)| (unsafe { UnsafeRef::from_raw(cde) }, reason))
+    }
+
+    fn evict(&mut self) -> Option<UnsafeRef<FbrEntry<K, V>>> {
+        let cde = self.victim()?;
+        self.unlink(&cde);
+        self.evictions += 1;
+        if let Some(growth) = &mut self.growth {
+            growth.ghosts.remember(&cde.key, self.capacity);
+        }
+        self.debug_check();
+        Some(cde)
+    }
+
+    /// Change the capacity to a value not below the current number of items.
+    ///
+    /// The aging threshold is scaled accordingly.
+    fn resize(&mut self, capacity: usize) {
+        debug_assert!(capacity >= self.len());
+        let age_threshold =
+            self.age_threshold as u128 * capacity as u128 / self.capacity.max(1) as u128;
+        self.age_threshold = age_threshold.min(usize::MAX as u128) as usize;
+        let old_capacity = std::mem::replace(&mut self.capacity, capacity);
+        self.rebuild_boundaries();
+        if old_capacity != capacity {
+            if let Some(observer) = &mut self.resize_observer {
+                observer(old_capacity, capacity);
+            }
+        }
+        self.debug_check();
+    }
+
+    /// Remove the given item from hash map, recency list and count chain.
+    ///
+    /// The region boundaries are moved so that the regions keep their proportions.
+    fn unlink(&mut self, cde: &UnsafeRef<FbrEntry<K, V>>) {
+        match self.chains.get_mut(cde.count) {
+            Some(chain) => {
+                unsafe { chain.cursor_mut_from_ptr(cde.as_ref()) }.remove();
+                self.occupied.remove(cde.count);
+            }
+            None => {
+                unsafe { self.overflow.cursor_mut_from_ptr(cde.as_ref()) }.remove();
+            }
+        }
+        unsafe {
+            let mut cursor = self.lru.cursor_mut_from_ptr(cde.as_ref());
+            if optr(&self.mid_boundary) == ptr(cde) {
+                self.mid_boundary = cursor.peek_next().clone_pointer();
+            }
+            if optr(&self.old_boundary) == ptr(cde) {
+                self.old_boundary = cursor.peek_next().clone_pointer();
+            }
+            cursor.remove();
+        };
+        retreat_boundaries(
+            cde.region,
+            &self.lru,
+            &mut self.mid_boundary,
+            &mut self.old_boundary,
+        );
+        self.hash.remove(&cde.key);
+        if cde.pinned {
+            self.pinned -= 1;
+        }
+        self.rebalance();
+    }
+}
+
+fn switch_chain<K, V, const C: usize>(
+    old_count: usize,
+    new_count: usize,
+    chains: &mut [LinkedList<ListChain<K, V>>; C],
+    overflow: &mut LinkedList<ListChain<K, V>>,
+    occupied: &mut Occupancy,
+    cde: &UnsafeRef<FbrEntry<K, V>>,
+) {
+    match chains.get_mut(old_count) {
+        Some(chain) => {
+            unsafe { chain.cursor_mut_from_ptr(cde.as_ref()) }.remove();
+            occupied.remove(old_count);
+        }
+        None => {
+            unsafe { overflow.cursor_mut_from_ptr(cde.as_ref()) }.remove();
+        }
+    }
+    match chains.get_mut(new_count) {
+        Some(chain) => {
+            chain.push_front(cde.clone());
+            occupied.add(new_count);
+        }
+        None => overflow.push_front(cde.clone()),
+    }
+}
+
+/// Number of items in each count chain, with a bit set of the non-empty ones
+struct Occupancy {
+    lens: Vec<usize>,
+    bits: Vec<u64>,
+}
+
+impl Occupancy {
+    fn new(chains: usize) -> Self {
+        Self {
+            lens: vec![0; chains],
+            bits: vec![0; chains.div_ceil(64)],
+        }
+    }
+
+    fn clear(&mut self) {
+        self.lens.fill(0);
+        self.bits.fill(0);
+    }
+
+    fn len(&self, chain: usize) -> usize {
+        self.lens[chain]
+    }
+
+    /// Record an item added to the given chain.
+    fn add(&mut self, chain: usize) {
+        self.lens[chain] += 1;
+        self.bits[chain / 64] |= 1 << (chain % 64);
+    }
+
+    /// Record an item removed from the given chain.
+    fn remove(&mut self, chain: usize) {
+        self.lens[chain] -= 1;
+        if self.lens[chain] == 0 {
+            self.bits[chain / 64] &= !(1 << (chain % 64));
+        }
+    }
+}
+
+/// Indices of the non-empty count chains, in ascending order.
+fn occupied_chains(occupied: &Occupancy) -> impl Iterator<Item = usize> + '_ {
+    occupied.bits.iter().enumerate().flat_map(|(word, &bits)| {
+        let mut bits = bits;
+        std::iter::from_fn(move || {
+            if bits == 0 {
+                return None;
+            }
+            let bit = bits.trailing_zeros() as usize;
+            bits &= bits - 1;
+            Some(word * 64 + bit)
+        })
+    })
+}
+
+/// Shift the boundaries after moving an item from the given region to the front.
+///
+/// Returns the number of items that thereby moved into the old region.
+fn move_boundaries<K, V>(
+    from_region: Region,
+    len: usize,
+    mid: usize,
+    old: usize,
+    lru: &LinkedList<ListLru<K, V>>,
+    mid_boundary: &mut Option<UnsafeRef<FbrEntry<K, V>>>,
+    old_boundary: &mut Option<UnsafeRef<FbrEntry<K, V>>>,
+) -> u64 {
+    if from_region > Region::New {
+        if let Some(mid) = mid_boundary {
             let cursor = unsafe { lru.cursor_from_ptr(mid.as_ref()) };
             let ptr = cursor.peek_prev().clone_pointer().unwrap();
             FbrEntry::region(&ptr, Region::Middle);
@@ -348,11 +2843,68 @@ fn move_boundaries<K, V>(
             let ptr = cursor.peek_prev().clone_pointer().unwrap();
             FbrEntry::region(&ptr, Region::Old);
             *old_boundary = Some(ptr);
+            return 1;
         } else if len == old + 1 {
             let ptr = lru.back().clone_pointer().unwrap();
             FbrEntry::region(&ptr, Region::Old);
             *old_boundary = Some(ptr);
+            return 1;
+        }
+    }
+    0
+}
+
+/// Inverse of `move_boundaries` after removing an item from the given region.
+fn retreat_boundaries<K, V>(
+    from_region: Region,
+    lru: &LinkedList<ListLru<K, V>>,
+    mid_boundary: &mut Option<UnsafeRef<FbrEntry<K, V>>>,
+    old_boundary: &mut Option<UnsafeRef<FbrEntry<K, V>>>,
+) {
+    // old boundary first, in case both point to the same item
+    if from_region < Region::Old {
+        if let Some(old) = old_boundary {
+            FbrEntry::region(old, Region::Middle);
+            let cursor = unsafe { lru.cursor_from_ptr(old.as_ref()) };
+            *old_boundary = cursor.peek_next().clone_pointer();
+        }
+    }
+    if from_region < Region::Middle {
+        if let Some(mid) = mid_boundary {
+            FbrEntry::region(mid, Region::New);
+            let cursor = unsafe { lru.cursor_from_ptr(mid.as_ref()) };
+            *mid_boundary = cursor.peek_next().clone_pointer();
+        }
+    }
+}
+
+/// Move a boundary by one item towards the front or the back of the list.
+///
+/// `before` and `after` are the regions of the item that changes sides.
+fn shift_boundary<K, V>(
+    towards_back: bool,
+    before: Region,
+    after: Region,
+    lru: &LinkedList<ListLru<K, V>>,
+    boundary: &mut Option<UnsafeRef<FbrEntry<K, V>>>,
+) {
+    if towards_back {
+        if let Some(cde) = boundary {
+            FbrEntry::region(cde, before);
+            let cursor = unsafe { lru.cursor_from_ptr(cde.as_ref()) };
+            *boundary = cursor.peek_next().clone_pointer();
+        }
+    } else {
+        let ptr = match boundary {
+            Some(cde) => unsafe { lru.cursor_from_ptr(cde.as_ref()) }
+                .peek_prev()
+                .clone_pointer(),
+            None => lru.back().clone_pointer(),
+        };
+        if let Some(ptr) = &ptr {
+            FbrEntry::region(ptr, after);
         }
+        *boundary = ptr;
     }
 }
 
@@ -0,0 +1,101 @@
+use crate::{FbrCache, Region};
+use std::hash::Hash;
+
+/// Set of keys with frequency-based replacement strategy.
+///
+/// This is an [`FbrCache`] without values, useful for tracking which keys are
+/// currently hot. Inserting a present key or touching it counts as a hit.
+pub struct FbrSet<K, const C_MAX: usize> {
+    cache: FbrCache<K, (), C_MAX>,
+}
+
+impl<K: Hash + Eq + Clone> FbrSet<K, 8> {
+    /// Create a new set with the given capacity.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            cache: FbrCache::new(capacity),
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone, const C: usize> FbrSet<K, C> {
+    /// Create a new set with the given capacity and aging threshold.
+    pub fn with_age_threshold(capacity: usize, age_threshold: usize) -> Self {
+        Self {
+            cache: FbrCache::with_age_threshold(capacity, age_threshold),
+        }
+    }
+
+    /// Insert the key, evicting another one if necessary.
+    ///
+    /// Returns `true` if the key was not present before; otherwise this acts like [`Self::touch`].
+    pub fn insert(&mut self, key: K) -> bool {
+        if self.cache.get(&key).is_some() {
+            return false;
+        }
+        self.cache.put_if_absent(key, ())
+    }
+
+    /// Returns `true` if the key is present, without updating usage count or recency.
+    pub fn contains(&self, key: &K) -> bool {
+        self.cache.contains_key(key)
+    }
+
+    /// Update usage count and recency of the key, returning `true` if it is present.
+    pub fn touch(&mut self, key: &K) -> bool {
+        self.cache.get(key).is_some()
+    }
+
+    /// Remove the key, returning `true` if it was present.
+    pub fn remove(&mut self, key: &K) -> bool {
+        self.cache.remove(key).is_some()
+    }
+
+    /// The usage count of the key, if present.
+    pub fn count(&self, key: &K) -> Option<usize> {
+        self.cache.count_of(key)
+    }
+
+    /// The `n` keys with the highest usage counts, hottest first.
+    pub fn hot_keys(&self, n: usize) -> Vec<&K> {
+        self.cache.hot_keys(n)
+    }
+}
+
+impl<K, const C: usize> FbrSet<K, C> {
+    /// The number of keys currently in the set.
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    /// Returns `true` if there are no keys in the set.
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
+
+    /// The maximum number of keys the set holds.
+    pub fn capacity(&self) -> usize {
+        self.cache.capacity()
+    }
+
+    /// Clears all keys from the set.
+    pub fn clear(&mut self) {
+        self.cache.clear()
+    }
+
+    /// An iterator over all keys together with their usage count and region, most recent first.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, usize, Region)> {
+        self.cache
+            .iter()
+            .map(|(k, _, count, region)| (k, count, region))
+    }
+}
+
+impl<K, const C: usize> std::fmt::Debug for FbrSet<K, C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FbrSet")
+            .field("capacity", &self.cache.capacity())
+            .field("items", &self.cache.len())
+            .finish()
+    }
+}
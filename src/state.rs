@@ -0,0 +1,254 @@
+use crate::{EvictPolicy, FbrCache, FbrEntry, PromotionPolicy, Region};
+use std::{collections::HashSet, hash::Hash};
+
+/// Owned snapshot of an [`FbrCache`], as produced by [`FbrCache::export_state`]
+///
+/// This is a plain data structure so that it can be persisted with whatever
+/// serialization mechanism is at hand. It holds all settings except callbacks like
+/// [`FbrCache::set_admission`]; learned state like the keys remembered for growing or
+/// as tombstones is not included.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheState<K, V> {
+    pub capacity: usize,
+    /// The absolute aging threshold, see [`FbrCache::with_absolute_age_threshold`].
+    pub age_threshold: usize,
+    pub max_count: usize,
+    pub evict_policy: EvictPolicy,
+    /// See [`FbrCache::set_scan_limit`].
+    pub scan_limit: usize,
+    pub promotion_policy: PromotionPolicy,
+    pub overwrite_on_put: bool,
+    /// Room beyond the capacity, see [`FbrCache::set_hard_capacity`].
+    pub slack: usize,
+    /// The maximum capacity, see [`FbrCache::set_grow_on_demand`].
+    pub grow_on_demand: Option<usize>,
+    /// The target aging cadence, see [`FbrCache::set_auto_age_threshold`].
+    pub auto_age_threshold: Option<u64>,
+    /// See [`FbrCache::set_freq_decay_on_miss`].
+    pub freq_decay_on_miss: usize,
+    /// Halvings per aging pass indexed by [`Region`], see [`FbrCache::set_region_aging`].
+    pub region_aging: [u32; 3],
+    /// The maximum number of tombstones, see [`FbrCache::set_tombstones`].
+    pub tombstones: usize,
+    /// All items with their usage counts, most recently used first.
+    pub entries: Vec<(K, V, usize)>,
+}
+
+/// Sizing parameters of an [`FbrCache`], as returned by [`FbrCache::into_parts`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheConfig {
+    pub capacity: usize,
+    /// The absolute aging threshold, see [`FbrCache::with_absolute_age_threshold`].
+    pub age_threshold: usize,
+    /// Number of items in the “new” region.
+    pub mid: usize,
+    /// Number of items in front of the “old” region.
+    pub old: usize,
+}
+
+/// All settings of an [`FbrCache`] in one place, for use with [`FbrCache::with_config`]
+///
+/// The capacity has no sensible default and must be set; the other fields default to the
+/// settings of [`FbrCache::new`]. Callbacks like [`FbrCache::set_admission`] are not
+/// covered and need to be installed on the created cache. There are no fields for the
+/// region sizes, since the “new” region always spans the first 30% of the items and the
+/// “old” region starts at 75%.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FbrConfig {
+    pub capacity: usize,
+    /// The aging threshold per slot, see [`FbrCache::with_age_threshold`].
+    pub age_threshold: usize,
+    pub max_count: usize,
+    pub evict_policy: EvictPolicy,
+    /// See [`FbrCache::set_scan_limit`].
+    pub scan_limit: usize,
+    pub promotion_policy: PromotionPolicy,
+    pub overwrite_on_put: bool,
+    /// See [`FbrCache::set_hard_capacity`], a value not above the capacity disables batching.
+    pub hard_capacity: usize,
+    /// The maximum capacity, see [`FbrCache::set_grow_on_demand`].
+    pub grow_on_demand: Option<usize>,
+    /// The target aging cadence, see [`FbrCache::set_auto_age_threshold`].
+    pub auto_age_threshold: Option<u64>,
+    /// See [`FbrCache::set_freq_decay_on_miss`].
+    pub freq_decay_on_miss: usize,
+    /// Halvings per aging pass indexed by [`Region`], see [`FbrCache::set_region_aging`].
+    pub region_aging: [u32; 3],
+    /// The maximum number of tombstones, see [`FbrCache::set_tombstones`].
+    pub tombstones: usize,
+}
+
+impl Default for FbrConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 0,
+            age_threshold: 100,
+            max_count: usize::MAX,
+            evict_policy: EvictPolicy::default(),
+            scan_limit: usize::MAX,
+            promotion_policy: PromotionPolicy::default(),
+            overwrite_on_put: false,
+            hard_capacity: 0,
+            grow_on_demand: None,
+            auto_age_threshold: None,
+            freq_decay_on_miss: 0,
+            region_aging: [1; 3],
+            tombstones: 0,
+        }
+    }
+}
+
+/// Invalid setting in an [`FbrConfig`], as reported by [`FbrCache::with_config`]
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ConfigError {
+    /// The capacity is below the minimum of 4 items.
+    CapacityTooSmall(usize),
+    /// The aging threshold is zero, so usage counts would be aged on every access.
+    ZeroAgeThreshold,
+    /// The ceiling for usage counts is zero, so no item could ever be counted as used.
+    ZeroMaxCount,
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::CapacityTooSmall(capacity) => {
+                write!(f, "cache capacity must be at least 4, not {capacity}")
+            }
+            ConfigError::ZeroAgeThreshold => write!(f, "aging threshold must not be zero"),
+            ConfigError::ZeroMaxCount => write!(f, "maximum usage count must not be zero"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl<K: Hash + Eq + Clone, V, const C: usize> FbrCache<K, V, C> {
+    /// Create a new cache with all settings taken from the given configuration.
+    pub fn with_config(config: FbrConfig) -> Result<Self, ConfigError> {
+        if config.capacity < 4 {
+            return Err(ConfigError::CapacityTooSmall(config.capacity));
+        }
+        if config.age_threshold == 0 {
+            return Err(ConfigError::ZeroAgeThreshold);
+        }
+        if config.max_count == 0 {
+            return Err(ConfigError::ZeroMaxCount);
+        }
+        let mut cache = Self::with_age_threshold(config.capacity, config.age_threshold);
+        cache.max_count = config.max_count;
+        cache.evict_policy = config.evict_policy;
+        cache.scan_limit = config.scan_limit;
+        cache.promotion_policy = config.promotion_policy;
+        cache.overwrite_on_put = config.overwrite_on_put;
+        cache.set_hard_capacity(config.hard_capacity);
+        if let Some(max_capacity) = config.grow_on_demand {
+            cache.set_grow_on_demand(max_capacity);
+        }
+        cache.set_auto_age_threshold(config.auto_age_threshold);
+        cache.miss_decay = config.freq_decay_on_miss;
+        cache.aging_shifts = config.region_aging;
+        cache.tombstone_limit = config.tombstones;
+        Ok(cache)
+    }
+
+    /// Take an owned snapshot of the contents and settings of this cache, see [`CacheState`].
+    pub fn export_state(&self) -> CacheState<K, V>
+    where
+        V: Clone,
+    {
+        CacheState {
+            capacity: self.capacity,
+            age_threshold: self.age_threshold,
+            max_count: self.max_count,
+            evict_policy: self.evict_policy,
+            scan_limit: self.scan_limit,
+            promotion_policy: self.promotion_policy,
+            overwrite_on_put: self.overwrite_on_put,
+            slack: self.slack,
+            grow_on_demand: self.growth.as_ref().map(|growth| growth.max_capacity),
+            auto_age_threshold: self.auto_age.as_ref().map(|auto| auto.interval),
+            freq_decay_on_miss: self.miss_decay,
+            region_aging: self.aging_shifts,
+            tombstones: self.tombstone_limit,
+            entries: self
+                .iter()
+                .map(|(k, v, count, _)| (k.clone(), v.clone(), count))
+                .collect(),
+        }
+    }
+
+    /// Reconstruct a cache from a snapshot taken with [`Self::export_state`].
+    ///
+    /// Recency order and usage counts are restored, the regions follow from the order.
+    /// Since the snapshot may have been edited by hand, only the first (i.e. most recent)
+    /// entry for each key is used and counts are clamped to `max_count`. Entries beyond
+    /// the capacity are dropped from the least recent end.
+    pub fn import_state(state: CacheState<K, V>) -> Self {
+        let mut cache = Self::with_absolute_age_threshold(state.capacity, state.age_threshold);
+        cache.max_count = state.max_count;
+        cache.evict_policy = state.evict_policy;
+        cache.scan_limit = state.scan_limit;
+        cache.promotion_policy = state.promotion_policy;
+        cache.overwrite_on_put = state.overwrite_on_put;
+        cache.slack = state.slack;
+        if let Some(max_capacity) = state.grow_on_demand {
+            cache.set_grow_on_demand(max_capacity);
+        }
+        cache.set_auto_age_threshold(state.auto_age_threshold);
+        cache.miss_decay = state.freq_decay_on_miss;
+        cache.aging_shifts = state.region_aging;
+        cache.tombstone_limit = state.tombstones;
+        let mut seen = HashSet::new();
+        let mut entries = state.entries;
+        entries.retain(|(key, ..)| seen.insert(key.clone()));
+        entries.truncate(state.capacity);
+        for (key, value, count) in entries.into_iter().rev() {
+            let count = count.min(cache.max_count);
+            cache.insert(key, value, count);
+        }
+        cache
+    }
+
+    /// Consume this cache, returning all items with usage count and region plus the sizing.
+    ///
+    /// Items are listed most recently used first. In contrast to [`Self::export_state`]
+    /// this moves the values out without cloning them.
+    pub fn into_parts(mut self) -> (Vec<(K, V, usize, Region)>, CacheConfig) {
+        let config = CacheConfig {
+            capacity: self.capacity,
+            age_threshold: self.age_threshold,
+            mid: self.mid,
+            old: self.old,
+        };
+        let entries = self
+            .take_entries()
+            .into_iter()
+            .map(|entry| {
+                let FbrEntry {
+                    key,
+                    value,
+                    count,
+                    region,
+                    ..
+                } = *entry;
+                (key, value, count, region)
+            })
+            .collect();
+        (entries, config)
+    }
+
+    /// Consume this cache, returning all items most recently used first.
+    ///
+    /// This is the plain LRU view of the contents, without usage counts or regions; for
+    /// comparing against LRU behaviour on the same infrastructure see [`EvictPolicy::Lru`].
+    pub fn into_lru_order(mut self) -> Vec<(K, V)> {
+        self.take_entries()
+            .into_iter()
+            .map(|entry| {
+                let FbrEntry { key, value, .. } = *entry;
+                (key, value)
+            })
+            .collect()
+    }
+}
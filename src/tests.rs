@@ -1,4 +1,7 @@
-use crate::{FbrCache, Region};
+use crate::{
+    ConfigError, EntryStatus, EvictPolicy, FbrCache, FbrCacheN, FbrConfig, FbrSet, PromotionPolicy,
+    Region,
+};
 use std::sync::atomic::{AtomicUsize, Ordering};
 
 fn s(s: &str) -> String {
@@ -52,6 +55,7 @@ fn smoke() {
             (&2, &s("2"), 0, Region::Old),
         ]
     );
+    cache.check_invariants().unwrap();
 }
 
 #[test]
@@ -74,7 +78,7 @@ fn evict_1() {
             (&3, &s("3"), 0, Region::Middle),
             (&2, &s("2"), 0, Region::Middle),
             (&1, &s("1"), 1, Region::Old),
-            (&0, &s("0"), 1, Region::Old)
+            (&0, &s("0"), 2, Region::Old)
         ]
     );
     cache.get(&1);
@@ -85,7 +89,7 @@ fn evict_1() {
             (&4, &s("4"), 0, Region::Middle),
             (&3, &s("3"), 0, Region::Middle),
             (&2, &s("2"), 0, Region::Old),
-            (&0, &s("0"), 1, Region::Old)
+            (&0, &s("0"), 2, Region::Old)
         ]
     );
     cache.put(5, "5".to_string());
@@ -96,9 +100,10 @@ fn evict_1() {
             (&1, &s("1"), 2, Region::Middle),
             (&4, &s("4"), 0, Region::Middle),
             (&3, &s("3"), 0, Region::Old),
-            (&0, &s("0"), 1, Region::Old),
+            (&0, &s("0"), 2, Region::Old),
         ]
     );
+    cache.check_invariants().unwrap();
 }
 
 #[test]
@@ -120,6 +125,7 @@ fn evict_3() {
             (&1, &s("1"), 3, Region::Old)
         ]
     );
+    cache.check_invariants().unwrap();
 }
 
 #[test]
@@ -128,6 +134,7 @@ fn aging() {
     for n in 0usize..5 {
         for i in 1..6 {
             cache.put(i, i.to_string());
+            cache.check_invariants().unwrap();
             assert_eq!(
                 cache.total_count,
                 (n * 5 + i as usize).saturating_sub(5),
@@ -177,6 +184,7 @@ fn aging() {
             (&4, &s("4"), 1, Region::Old),
         ]
     );
+    cache.check_invariants().unwrap();
 }
 
 #[test]
@@ -200,6 +208,7 @@ fn clear() {
         cache.put(i, X(&counter));
     }
     assert_eq!(counter.load(Ordering::Relaxed), 7);
+    cache.check_invariants().unwrap();
     drop(cache);
     assert_eq!(counter.load(Ordering::Relaxed), 12);
 }
@@ -221,4 +230,2886 @@ fn prio() {
             (&0, &s("0"), 1, Region::Old),
         ]
     );
+    cache.check_invariants().unwrap();
+}
+
+#[test]
+fn rebuild_with_capacity() {
+    let mut cache = FbrCache::<u32, u32, 8>::new(20);
+    for round in 0..11 {
+        for i in 0..20 - round {
+            cache.put(i, i);
+        }
+    }
+    let mut before = cache
+        .iter()
+        .map(|(k, _, count, _)| (*k, count))
+        .collect::<Vec<_>>();
+    before.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+    assert_eq!(before[9].1, 10);
+    assert_eq!(before[10].1, 9);
+
+    let cache = cache.rebuild_with_capacity(10);
+    assert_eq!(cache.len(), 10);
+    let mut after = cache
+        .iter()
+        .map(|(k, v, count, _)| {
+            assert_eq!(k, v);
+            (*k, count)
+        })
+        .collect::<Vec<_>>();
+    after.sort();
+    assert_eq!(after, (0..10).map(|k| (k, 10)).collect::<Vec<_>>());
+    assert_eq!(cache.total_count, 100);
+    cache.check_invariants().unwrap();
+}
+
+#[test]
+fn contains_in_region() {
+    let mut cache = FbrCache::<u32, String, 3>::with_age_threshold(10, 4);
+    for i in 0..10 {
+        cache.put(i, i.to_string());
+    }
+    cache.get(&2);
+    let regions = cache.iter().map(|(k, _, _, r)| (*k, r)).collect::<Vec<_>>();
+    for (key, region) in regions {
+        assert_eq!(cache.region_of(&key), Some(region));
+        for r in [Region::New, Region::Middle, Region::Old] {
+            assert_eq!(cache.contains_in_region(&key, r), r == region);
+        }
+    }
+    assert_eq!(cache.region_of(&10), None);
+    assert!(!cache.contains_in_region(&10, Region::New));
+    cache.check_invariants().unwrap();
+}
+
+#[test]
+fn prune_to() {
+    let mut cache = FbrCache::<u32, String, 3>::with_age_threshold(10, 4);
+    for i in 0..10 {
+        cache.put(i, i.to_string());
+    }
+    cache.get(&1);
+    cache.get(&4);
+    assert_eq!(cache.prune_to(3), 7);
+    assert_eq!(
+        cache.iter().collect::<Vec<_>>(),
+        vec![
+            (&4, &s("4"), 1, Region::Middle),
+            (&1, &s("1"), 1, Region::Middle),
+            (&9, &s("9"), 0, Region::Old),
+        ]
+    );
+    assert_eq!(cache.prune_to(3), 0);
+
+    for i in 10..20 {
+        cache.put(i, i.to_string());
+    }
+    assert_eq!(cache.len(), 10);
+    cache.check_invariants().unwrap();
+}
+
+#[test]
+fn evict_policy() {
+    for (policy, victim) in [
+        (EvictPolicy::LowestCount, 1),
+        (EvictPolicy::LruBelow(1), 1),
+        (EvictPolicy::LruBelow(2), 0),
+        (EvictPolicy::Lru, 0),
+    ] {
+        let mut cache = FbrCache::<u32, String, 3>::with_age_threshold(10, 4);
+        assert_eq!(cache.evict_policy(), EvictPolicy::LowestCount);
+        cache.set_evict_policy(policy);
+        cache.put_prio(0, 0.to_string());
+        for i in 1..10 {
+            cache.put(i, i.to_string());
+        }
+        cache.put(10, 10.to_string());
+        cache.check_invariants().unwrap();
+        let keys = cache.iter().map(|(k, ..)| *k).collect::<Vec<_>>();
+        assert_eq!(keys.len(), 10);
+        assert!(!keys.contains(&victim), "{:?}", policy);
+    }
+}
+
+#[test]
+fn lru_order() {
+    let mut cache = FbrCache::<u32, String, 3>::with_age_threshold(5, 4);
+    assert_eq!(cache.lru_order(), vec![]);
+    for i in 0..7 {
+        cache.put(i, i.to_string());
+    }
+    cache.get(&3);
+    let order = cache.lru_order();
+    assert_eq!(order, vec![3, 6, 5, 4, 2]);
+    assert_eq!(order, cache.iter().map(|(k, ..)| *k).collect::<Vec<_>>());
+    assert_eq!(order, cache.lru_order());
+    cache.check_invariants().unwrap();
+}
+
+#[test]
+fn age_events() {
+    let mut cache = FbrCache::<u32, String, 3>::with_age_threshold(5, 4);
+    for _ in 0..5 {
+        for i in 1..6 {
+            cache.put(i, i.to_string());
+        }
+    }
+    assert_eq!(cache.age_events(), 0);
+    assert_eq!(cache.accesses_since_age(), 20);
+    cache.get(&1);
+    assert_eq!(cache.age_events(), 1);
+    assert_eq!(cache.accesses_since_age(), 0);
+
+    for n in 0..100 {
+        let events = cache.age_events();
+        let total = cache.total_count;
+        cache.put(n % 7, n.to_string());
+        if cache.age_events() > events {
+            assert_eq!(cache.age_events(), events + 1);
+            assert_eq!(total, cache.age_threshold);
+            assert_eq!(cache.accesses_since_age(), 0);
+        } else {
+            assert!(cache.total_count <= cache.age_threshold);
+        }
+    }
+    assert!(cache.age_events() > 1);
+    cache.check_invariants().unwrap();
+}
+
+#[test]
+fn put_prio_n() {
+    let mut cache = FbrCache::<u32, String, 4>::with_age_threshold(10, 4);
+    cache.put_prio_n(2, 2.to_string(), 3);
+    cache.put_prio_n(1, 1.to_string(), 100);
+    cache.put(0, 0.to_string());
+    assert_eq!(
+        cache.iter().map(|(k, _, c, _)| (*k, c)).collect::<Vec<_>>(),
+        vec![(0, 0), (1, 3), (2, 3)]
+    );
+
+    for round in 0..4 {
+        for i in 10..20 {
+            cache.put(round * 100 + i, i.to_string());
+        }
+    }
+    let keys = cache.iter().map(|(k, ..)| *k).collect::<Vec<_>>();
+    assert!(!keys.contains(&0));
+    assert!(keys.contains(&1));
+    assert!(keys.contains(&2));
+    cache.check_invariants().unwrap();
+}
+
+#[test]
+fn extend_prio() {
+    let mut cache = FbrCache::<u32, String, 3>::with_age_threshold(20, 4);
+    cache.extend_prio((0..3).map(|i| (i, i.to_string())));
+    assert_eq!(
+        cache.iter().map(|(k, _, c, _)| (*k, c)).collect::<Vec<_>>(),
+        vec![(2, 1), (1, 1), (0, 1)]
+    );
+
+    for i in 10..90 {
+        cache.put(i, i.to_string());
+    }
+    let keys = cache.iter().map(|(k, ..)| *k).collect::<Vec<_>>();
+    assert_eq!(keys.len(), 20);
+    for i in 0..3 {
+        assert!(keys.contains(&i));
+    }
+    cache.check_invariants().unwrap();
+}
+
+#[test]
+fn put_ref() {
+    let mut cache = FbrCache::<u32, String, 3>::with_age_threshold(5, 4);
+    cache.set_admission(|k| *k != 3);
+    assert_eq!(cache.put_ref(1, s("one")), "one");
+    assert_eq!(cache.put_ref(1, s("uno")), "one");
+    cache.set_overwrite_on_put(true);
+    assert_eq!(cache.put_ref(1, s("uno")), "uno");
+    assert_eq!(cache.put_ref(3, s("three")), "three");
+
+    let value = cache.put_ref(2, s("two")) as *const String;
+    cache.put(4, s("four"));
+    cache.get(&1);
+    assert_eq!(cache.peek(&2).map(|v| v as *const String), Some(value));
+    assert_eq!(cache.len(), 4);
+    cache.check_invariants().unwrap();
+}
+
+#[test]
+fn effective_age_threshold() {
+    let cache = FbrCache::<u32, String, 3>::with_age_threshold(5, 4);
+    assert_eq!(cache.effective_age_threshold(), 20);
+    let cache = FbrCache::<u32, String, 3>::with_absolute_age_threshold(5, 20);
+    assert_eq!(cache.effective_age_threshold(), 20);
+
+    let cache = FbrCache::<u32, String, 3>::with_age_threshold(5, usize::MAX / 4);
+    assert_eq!(cache.effective_age_threshold(), usize::MAX);
+    let cache = FbrCache::<u32, String, 3>::with_absolute_age_threshold(5, usize::MAX - 1);
+    assert_eq!(cache.effective_age_threshold(), usize::MAX - 1);
+}
+
+#[test]
+fn drain_region() {
+    let mut cache = FbrCache::<u32, String, 3>::with_age_threshold(10, 4);
+    for i in 0..10 {
+        cache.put(i, i.to_string());
+    }
+    cache.get(&2);
+    assert_eq!(
+        cache.drain_region(Region::Old),
+        vec![(3, s("3"), 0), (1, s("1"), 0), (0, s("0"), 0)]
+    );
+    assert_eq!(
+        cache.iter().collect::<Vec<_>>(),
+        vec![
+            (&2, &s("2"), 1, Region::New),
+            (&9, &s("9"), 0, Region::New),
+            (&8, &s("8"), 0, Region::Middle),
+            (&7, &s("7"), 0, Region::Middle),
+            (&6, &s("6"), 0, Region::Middle),
+            (&5, &s("5"), 0, Region::Old),
+            (&4, &s("4"), 0, Region::Old),
+        ]
+    );
+    // the remaining items form proportional regions again
+    assert_eq!(
+        cache.drain_region(Region::Old),
+        vec![(5, s("5"), 0), (4, s("4"), 0)]
+    );
+
+    for i in 10..14 {
+        cache.put(i, i.to_string());
+    }
+    assert_eq!(
+        cache.iter().map(|(k, .., r)| (*k, r)).collect::<Vec<_>>(),
+        vec![
+            (13, Region::New),
+            (12, Region::New),
+            (11, Region::Middle),
+            (10, Region::Middle),
+            (2, Region::Middle),
+            (9, Region::Middle),
+            (8, Region::Old),
+            (7, Region::Old),
+            (6, Region::Old),
+        ]
+    );
+    cache.check_invariants().unwrap();
+}
+
+#[test]
+fn get_or_default_mut() {
+    let mut cache = FbrCache::<u32, Vec<u32>, 3>::with_age_threshold(5, 4);
+    for i in 0..4 {
+        cache.get_or_default_mut(1).push(i);
+        cache.get_or_default_mut(i + 10).push(i);
+    }
+    assert_eq!(cache.get(&1), Some(&vec![0, 1, 2, 3]));
+    assert_eq!(cache.get(&12), Some(&vec![2]));
+    assert_eq!(cache.len(), 5);
+    cache.check_invariants().unwrap();
+}
+
+#[test]
+fn get_mut_or_insert_with() {
+    let mut cache = FbrCache::<u32, u32, 3>::with_age_threshold(5, 4);
+    let mut calls = 0;
+    for i in 1..=4 {
+        *cache.get_mut_or_insert_with(1, || {
+            calls += 1;
+            100
+        }) += i;
+    }
+    assert_eq!(calls, 1);
+    assert_eq!(cache.peek(&1), Some(&110));
+    assert_eq!(cache.count_of(&1), Some(3));
+    assert_eq!(cache.misses(), 1);
+    assert_eq!(cache.hits(), 3);
+    cache.check_invariants().unwrap();
+}
+
+#[test]
+fn get_or_insert_with_status() {
+    let mut cache = FbrCache::<u32, String, 3>::with_age_threshold(5, 4);
+    let mut calls = 0;
+    let mut f = |i: u32| {
+        calls += 1;
+        i.to_string()
+    };
+    assert_eq!(
+        cache.get_or_insert_with_status(1, || f(1)),
+        (&s("1"), false)
+    );
+    assert_eq!(cache.get_or_insert_with_status(1, || f(2)), (&s("1"), true));
+    assert_eq!(
+        cache.get_or_insert_with_status(2, || f(2)),
+        (&s("2"), false)
+    );
+    assert_eq!(cache.get_or_insert_with_status(1, || f(3)), (&s("1"), true));
+    assert_eq!(calls, 2);
+    assert_eq!((cache.hits(), cache.misses()), (2, 2));
+    cache.check_invariants().unwrap();
+}
+
+#[test]
+fn contains_all_any() {
+    let mut cache = FbrCache::<u32, String, 3>::with_age_threshold(5, 4);
+    for i in 0..3 {
+        cache.put(i, i.to_string());
+    }
+    let order = cache.lru_order();
+    assert!(cache.contains_all(&[0, 2]));
+    assert!(!cache.contains_all(&[0, 7]));
+    assert!(cache.contains_all(&[]));
+    assert!(cache.contains_any(&[7, 1]));
+    assert!(!cache.contains_any(&[7, 8]));
+    assert!(!cache.contains_any(&[]));
+    assert_eq!(cache.lru_order(), order);
+    assert_eq!((cache.hits(), cache.misses()), (0, 0));
+}
+
+#[test]
+fn front_back() {
+    let mut cache = FbrCache::<u32, String, 3>::with_age_threshold(5, 4);
+    assert_eq!((cache.front(), cache.back()), (None, None));
+    for i in 0..7 {
+        cache.put(i, i.to_string());
+    }
+    cache.get(&4);
+    let items = cache.iter().map(|(k, v, ..)| (k, v)).collect::<Vec<_>>();
+    assert_eq!(cache.front(), items.first().copied());
+    assert_eq!(cache.back(), items.last().copied());
+    assert_eq!(cache.front(), Some((&4, &s("4"))));
+    assert_eq!(cache.back(), Some((&2, &s("2"))));
+}
+
+#[test]
+fn get_cloned_many() {
+    let mut cache = FbrCache::<u32, String, 3>::with_age_threshold(5, 4);
+    for i in 0..5 {
+        cache.put(i, i.to_string());
+    }
+    let found = cache.get_cloned_many(&[4, 7, 0, 9]);
+    assert_eq!(
+        found,
+        [(4, s("4")), (0, s("0"))]
+            .into_iter()
+            .collect::<std::collections::HashMap<_, _>>()
+    );
+    assert_eq!((cache.hits(), cache.misses()), (2, 2));
+    assert_eq!(cache.lru_order()[0], 0);
+    assert_eq!(cache.count_of(&0), Some(1));
+    cache.check_invariants().unwrap();
+}
+
+#[test]
+fn is_protected() {
+    let mut cache = FbrCache::<u32, String, 3>::with_age_threshold(10, 4);
+    for i in 0..10 {
+        cache.put(i, i.to_string());
+    }
+    for n in 10..60 {
+        cache.get(&(n % 4));
+        let keys = cache.iter().map(|(k, ..)| *k).collect::<Vec<_>>();
+        let unprotected = keys
+            .iter()
+            .filter(|k| !cache.is_protected(k))
+            .copied()
+            .collect::<Vec<_>>();
+        assert_eq!(unprotected.len(), 1);
+        cache.put(n, n.to_string());
+        cache.check_invariants().unwrap();
+        for k in keys {
+            assert_eq!(cache.region_of(&k).is_some(), k != unprotected[0]);
+        }
+    }
+    assert!(!cache.is_protected(&1000));
+}
+
+#[test]
+fn touch_many() {
+    let mut cache = FbrCache::<u32, String, 3>::with_age_threshold(10, 4);
+    for i in 0..10 {
+        cache.put(i, i.to_string());
+    }
+    assert_eq!(cache.touch_many(&[2, 11, 5, 0]), 3);
+    assert_eq!(
+        cache.iter().take(4).collect::<Vec<_>>(),
+        vec![
+            (&2, &s("2"), 1, Region::New),
+            (&5, &s("5"), 1, Region::New),
+            (&0, &s("0"), 1, Region::New),
+            (&9, &s("9"), 0, Region::Middle),
+        ]
+    );
+    assert_eq!(cache.len(), 10);
+    cache.check_invariants().unwrap();
+}
+
+#[test]
+fn check_invariants() {
+    let mut cache = FbrCache::<u32, u32, 4>::with_age_threshold(20, 2);
+    cache.check_invariants().unwrap();
+    for n in 0u32..2000 {
+        let key = n.wrapping_mul(2654435761) % 37;
+        match n % 7 {
+            0 => {
+                cache.put_prio(key, n);
+            }
+            1 => {
+                cache.touch_many(&[key, key + 1, key + 2]);
+            }
+            2 if n % 100 == 2 => {
+                cache.prune_to(12);
+            }
+            3 if n % 150 == 3 => {
+                cache.drain_region(Region::Middle);
+            }
+            4 => {
+                cache.remove(&key);
+            }
+            _ => {
+                if cache.get(&key).is_none() {
+                    cache.put(key, n);
+                }
+            }
+        }
+        cache.check_invariants().unwrap();
+    }
+    assert!(cache.age_events() > 0);
+}
+
+#[test]
+fn get_quiet() {
+    let mut cache = FbrCache::<u32, String, 3>::with_age_threshold(10, 4);
+    for i in 0..10 {
+        cache.put(i, i.to_string());
+    }
+    let before = cache
+        .iter()
+        .map(|(k, v, c, r)| (*k, v.clone(), c, r))
+        .collect::<Vec<_>>();
+    for i in 0..12 {
+        assert_eq!(cache.get_quiet(&i), cache.peek(&i));
+        assert_eq!(
+            cache.get_quiet(&i),
+            (i < 10).then(|| i.to_string()).as_ref()
+        );
+    }
+    let after = cache
+        .iter()
+        .map(|(k, v, c, r)| (*k, v.clone(), c, r))
+        .collect::<Vec<_>>();
+    assert_eq!(before, after);
+    assert_eq!(cache.total_count, 0);
+    cache.check_invariants().unwrap();
+}
+
+#[test]
+fn has_been_full() {
+    let mut cache = FbrCache::<u32, String, 3>::with_age_threshold(5, 4);
+    let mut transitions = vec![];
+    let mut full = cache.has_been_full();
+    for i in 0..30 {
+        match i {
+            12 => {
+                cache.prune_to(1);
+            }
+            20 => cache.clear(),
+            _ => {
+                cache.put(i, i.to_string());
+            }
+        }
+        if cache.has_been_full() != full {
+            full = cache.has_been_full();
+            transitions.push((i, cache.len()));
+        }
+    }
+    assert_eq!(transitions, vec![(4, 5)]);
+    assert!(cache.has_been_full());
+}
+
+#[test]
+fn remove_if() {
+    let mut cache = FbrCache::<u32, String, 3>::with_age_threshold(10, 4);
+    for i in 0..10 {
+        cache.put(i, i.to_string());
+    }
+    cache.get(&3);
+    let before = cache
+        .iter()
+        .map(|(k, v, c, r)| (*k, v.clone(), c, r))
+        .collect::<Vec<_>>();
+    assert_eq!(cache.remove_if(&3, |_, v| v.len() > 1), None);
+    assert_eq!(cache.remove_if(&11, |_, _| unreachable!()), None);
+    let after = cache
+        .iter()
+        .map(|(k, v, c, r)| (*k, v.clone(), c, r))
+        .collect::<Vec<_>>();
+    assert_eq!(before, after);
+
+    assert_eq!(
+        cache.remove_if(&3, |k, v| *k == 3 && v == "3"),
+        Some(s("3"))
+    );
+    assert_eq!(cache.remove(&7), Some(s("7")));
+    assert_eq!(cache.remove(&7), None);
+    assert_eq!(
+        cache.iter().collect::<Vec<_>>(),
+        vec![
+            (&9, &s("9"), 0, Region::New),
+            (&8, &s("8"), 0, Region::New),
+            (&6, &s("6"), 0, Region::Middle),
+            (&5, &s("5"), 0, Region::Middle),
+            (&4, &s("4"), 0, Region::Middle),
+            (&2, &s("2"), 0, Region::Middle),
+            (&1, &s("1"), 0, Region::Old),
+            (&0, &s("0"), 0, Region::Old),
+        ]
+    );
+    cache.check_invariants().unwrap();
+    for i in [9, 0, 4, 6, 8] {
+        cache.remove(&i);
+        cache.check_invariants().unwrap();
+    }
+    assert_eq!(cache.lru_order(), vec![5, 2, 1]);
+}
+
+#[test]
+fn admission() {
+    let mut cache = FbrCache::<u32, String, 3>::with_age_threshold(5, 4);
+    for i in 0..5 {
+        cache.put(i, i.to_string());
+    }
+    cache.set_admission(|k| k % 2 == 0);
+    let before = cache.lru_order();
+    for i in (5..20).step_by(2) {
+        cache.put(i, i.to_string());
+        cache.put_prio(i + 100, i.to_string());
+    }
+    assert_eq!(cache.lru_order(), before);
+    assert_eq!(cache.get(&3), Some(&s("3")));
+
+    cache.put(20, s("20"));
+    assert_eq!(cache.len(), 5);
+    assert_eq!(cache.peek(&20), Some(&s("20")));
+    cache.check_invariants().unwrap();
+}
+
+#[test]
+fn frequency_sum() {
+    let mut cache = FbrCache::<u32, String, 3>::with_age_threshold(10, 100);
+    for n in 0..200u32 {
+        let key = n * n % 13;
+        if cache.get(&key).is_none() {
+            cache.put(key, key.to_string());
+        }
+    }
+    for region in [Region::New, Region::Middle, Region::Old] {
+        let expected = cache
+            .iter()
+            .filter(|(.., r)| *r == region)
+            .map(|(_, _, c, _)| c)
+            .sum::<usize>();
+        assert_eq!(cache.frequency_sum(region), expected);
+    }
+    assert!(cache.frequency_sum(Region::New) > 0);
+    cache.check_invariants().unwrap();
+}
+
+#[test]
+fn fixed_capacity() {
+    type Cache = FbrCacheN<u32, String, 16, 3>;
+    assert_eq!((Cache::MID, Cache::OLD), (4, 12));
+    let mut cache = Cache::new();
+    for i in 0..20 {
+        cache.put(i, i.to_string());
+    }
+    assert_eq!(cache.len(), 16);
+    assert_eq!(cache.capacity(), 16);
+    assert_eq!(cache.region_of(&16), Some(Region::New));
+    assert_eq!(cache.region_of(&15), Some(Region::Middle));
+    assert_eq!(cache.region_of(&8), Some(Region::Middle));
+    assert_eq!(cache.region_of(&7), Some(Region::Old));
+    cache.check_invariants().unwrap();
+    let cache = cache.into_inner();
+    assert_eq!(cache.len(), 16);
+}
+
+#[test]
+fn iter_from() {
+    let mut cache = FbrCache::<u32, String, 3>::with_age_threshold(10, 4);
+    for i in 0..10 {
+        cache.put(i, i.to_string());
+    }
+    cache.get(&4);
+    let all = cache.iter().collect::<Vec<_>>();
+    for (idx, (k, ..)) in all.iter().enumerate() {
+        assert_eq!(cache.iter_from(k).collect::<Vec<_>>(), all[idx..]);
+    }
+    assert_eq!(cache.iter_from(&10).count(), 0);
+}
+
+#[test]
+fn entry_prio() {
+    let mut cache = FbrCache::<u32, String, 3>::with_age_threshold(5, 4);
+    cache.put_prio(0, s("0"));
+    assert_eq!(cache.entry(1).or_insert_prio(s("1")), "1");
+    assert_eq!(cache.entry(2).or_insert_with_prio(|| s("2")), "2");
+    cache.entry(3).or_insert(s("3")).push('!');
+    assert_eq!(cache.entry(3).or_insert_with_prio(|| unreachable!()), "3!");
+    assert_eq!(
+        cache.iter().map(|(k, _, c, _)| (*k, c)).collect::<Vec<_>>(),
+        vec![(3, 0), (2, 1), (1, 1), (0, 1)]
+    );
+    match cache.entry(0) {
+        crate::Entry::Occupied(mut e) => {
+            assert_eq!(e.key(), &0);
+            assert_eq!(e.count(), 2);
+            e.get_mut().push('?');
+            assert_eq!(e.get(), "0?");
+            assert_eq!(e.remove(), "0?");
+        }
+        crate::Entry::Vacant(_) => unreachable!(),
+    }
+    assert_eq!(cache.len(), 3);
+    cache.check_invariants().unwrap();
+}
+
+#[test]
+fn peek_iter() {
+    let mut cache = FbrCache::<u32, String, 3>::with_age_threshold(10, 4);
+    for i in 0..10 {
+        cache.put(i, i.to_string());
+    }
+    let all = cache.iter().collect::<Vec<_>>();
+    let mut iter = cache.peek_iter();
+    assert_eq!(iter.next(), Some(all[0]));
+    assert_eq!(iter.next(), Some(all[1]));
+    let fork = iter.clone();
+    assert_eq!(iter.collect::<Vec<_>>(), all[2..]);
+    assert_eq!(fork.collect::<Vec<_>>(), all[2..]);
+    assert_eq!(cache.peek_iter().collect::<Vec<_>>(), all);
+}
+
+#[test]
+fn recency_rank() {
+    let mut cache = FbrCache::<u32, String, 3>::with_age_threshold(10, 4);
+    for i in 0..12 {
+        cache.put(i, i.to_string());
+    }
+    cache.get(&5);
+    for (rank, (k, ..)) in cache.iter().enumerate() {
+        assert_eq!(cache.recency_rank(k), Some(rank));
+    }
+    assert_eq!(cache.recency_rank(&5), Some(0));
+    assert_eq!(cache.recency_rank(&0), None);
+}
+
+#[test]
+fn put_if_absent() {
+    let mut cache = FbrCache::<u32, String, 3>::with_age_threshold(5, 4);
+    for i in 0..5 {
+        assert!(cache.put_if_absent(i, i.to_string()));
+    }
+    let before = cache
+        .iter()
+        .map(|(k, v, c, r)| (*k, v.clone(), c, r))
+        .collect::<Vec<_>>();
+    assert!(!cache.put_if_absent(0, s("zero")));
+    assert!(cache.contains_key(&0));
+    let after = cache
+        .iter()
+        .map(|(k, v, c, r)| (*k, v.clone(), c, r))
+        .collect::<Vec<_>>();
+    assert_eq!(before, after);
+
+    cache.put(0, s("zero"));
+    assert_eq!(cache.iter().next(), Some((&0, &s("0"), 1, Region::New)));
+    assert!(!cache.contains_key(&5));
+    cache.check_invariants().unwrap();
+}
+
+#[test]
+fn fbr_set() {
+    let mut set = FbrSet::<u32, 4>::with_age_threshold(10, 100);
+    for round in 0..20u32 {
+        for hot in 0..3 {
+            set.insert(hot);
+        }
+        for cold in 0..7 {
+            assert!(set.insert(1000 + round * 10 + cold));
+        }
+    }
+    for hot in 0..3 {
+        assert!(set.contains(&hot));
+        assert!(set.count(&hot).unwrap() > 10);
+    }
+    let mut hot = set.hot_keys(3).into_iter().copied().collect::<Vec<_>>();
+    hot.sort();
+    assert_eq!(hot, vec![0, 1, 2]);
+    assert_eq!(set.len(), 10);
+    assert_eq!(set.iter().filter(|(_, c, _)| *c > 0).count(), 3);
+
+    assert!(!set.insert(0));
+    assert!(set.touch(&1));
+    assert!(!set.touch(&3));
+    assert!(set.remove(&2));
+    assert!(!set.contains(&2));
+    assert_eq!(set.count(&2), None);
+}
+
+#[test]
+fn grow_on_demand() {
+    let mut cache = FbrCache::<u32, u32, 3>::with_age_threshold(8, 4);
+    cache.set_grow_on_demand(40);
+    let mut capacities = vec![];
+    for w in [10, 14, 20, 28, 36, 50] {
+        let mut misses = 0;
+        for _ in 0..3 {
+            misses = 0;
+            for k in 0..w {
+                if cache.get(&k).is_none() {
+                    misses += 1;
+                    cache.put(k, k);
+                }
+            }
+            cache.check_invariants().unwrap();
+        }
+        assert_eq!(misses == 0, w <= 40, "w={}", w);
+        capacities.push(cache.capacity());
+    }
+    assert_eq!(capacities, vec![16, 16, 32, 32, 40, 40]);
+    assert_eq!(cache.effective_age_threshold(), 160);
+}
+
+#[test]
+fn evictions() {
+    let mut cache = FbrCache::<u32, String, 3>::with_age_threshold(5, 4);
+    for n in 0..23 {
+        cache.put(n % 11, n.to_string());
+    }
+    assert_eq!(cache.evictions(), 18);
+    assert_eq!(cache.remove(&0), Some(s("22")));
+    assert_eq!(cache.remove(&7), Some(s("18")));
+    assert_eq!(cache.evictions(), 18);
+    cache.prune_to(1);
+    assert_eq!(cache.evictions(), 20);
+    cache.reset_stats();
+    assert_eq!(cache.evictions(), 0);
+    assert_eq!(cache.age_events(), 0);
+}
+
+#[test]
+fn entry_ref() {
+    static CLONES: AtomicUsize = AtomicUsize::new(0);
+
+    #[derive(PartialEq, Eq, Hash, Debug)]
+    struct Key(u32);
+    impl Clone for Key {
+        fn clone(&self) -> Self {
+            CLONES.fetch_add(1, Ordering::Relaxed);
+            Key(self.0)
+        }
+    }
+    impl std::borrow::Borrow<u32> for Key {
+        fn borrow(&self) -> &u32 {
+            &self.0
+        }
+    }
+    impl From<&u32> for Key {
+        fn from(k: &u32) -> Self {
+            Key(*k)
+        }
+    }
+
+    let mut cache = FbrCache::<Key, u32, 3>::with_age_threshold(5, 4);
+    *cache.entry_ref(&1).or_insert(0) += 1;
+    let clones = CLONES.load(Ordering::Relaxed);
+    for _ in 0..5 {
+        *cache.entry_ref(&1).or_insert_with(|| unreachable!()) += 1;
+    }
+    assert_eq!(CLONES.load(Ordering::Relaxed), clones);
+    assert_eq!(cache.peek(&Key(1)), Some(&6));
+
+    match cache.entry_ref(&2) {
+        crate::EntryRef::Vacant(e) => {
+            assert_eq!(e.key(), &2);
+            e.insert_with_key(|k| Key(*k), 7);
+        }
+        crate::EntryRef::Occupied(_) => unreachable!(),
+    }
+    assert_eq!(cache.peek(&Key(2)), Some(&7));
+    cache.check_invariants().unwrap();
+}
+
+#[test]
+#[should_panic(expected = "differs from the entry key")]
+fn entry_ref_insert_with_mismatched_key() {
+    let mut cache = FbrCache::<String, u32, 3>::with_age_threshold(5, 4);
+    cache.put(s("x"), 1);
+    match cache.entry_ref("y") {
+        crate::EntryRef::Vacant(e) => {
+            e.insert_with_key(|_| s("x"), 2);
+        }
+        crate::EntryRef::Occupied(_) => unreachable!(),
+    }
+}
+
+#[test]
+fn clear_and_reset_stats() {
+    let mut cache = FbrCache::<u32, u32, 3>::with_age_threshold(5, 4);
+    for n in 0..30 {
+        cache.put(n % 5, n);
+    }
+    cache.get(&1);
+    cache.put(10, 10);
+    let evictions = cache.evictions();
+    let age_events = cache.age_events();
+    assert!(evictions > 0);
+    assert!(age_events > 0);
+
+    cache.clear();
+    assert!(cache.is_empty());
+    assert_eq!(cache.evictions(), evictions);
+    assert_eq!(cache.age_events(), age_events);
+
+    cache.put(1, 1);
+    cache.clear_and_reset_stats();
+    assert!(cache.is_empty());
+    assert_eq!(cache.evictions(), 0);
+    assert_eq!(cache.age_events(), 0);
+    cache.check_invariants().unwrap();
+}
+
+#[test]
+fn promotion_policy() {
+    let mut cache = FbrCache::<u32, String, 3>::with_age_threshold(10, 4);
+    assert_eq!(cache.promotion_policy(), PromotionPolicy::Always);
+    cache.set_promotion_policy(PromotionPolicy::NotInNew);
+    for i in 0..10 {
+        cache.put(i, i.to_string());
+    }
+    assert_eq!(cache.region_of(&8), Some(Region::New));
+    let order = cache.lru_order();
+    for _ in 0..3 {
+        assert_eq!(cache.get(&8), Some(&s("8")));
+    }
+    assert_eq!(cache.lru_order(), order);
+    assert_eq!(cache.count_of(&8), Some(0));
+
+    // items outside the “new” region are still promoted
+    cache.get(&0);
+    assert_eq!(cache.lru_order()[0], 0);
+    assert_eq!(cache.count_of(&0), Some(1));
+    cache.check_invariants().unwrap();
+
+    cache.set_promotion_policy(PromotionPolicy::Always);
+    cache.get(&8);
+    assert_eq!(cache.lru_order()[0], 8);
+    cache.check_invariants().unwrap();
+}
+
+#[test]
+fn max_count() {
+    let mut cache = FbrCache::<u32, u32, 8>::with_age_threshold(10, 1000);
+    assert_eq!(cache.max_count(), usize::MAX);
+    for n in 0..200 {
+        cache.put(n % 12, n);
+        cache.get(&(n % 3));
+    }
+    assert!(cache.count_of(&0).unwrap() > 3);
+
+    cache.set_max_count(3);
+    assert_eq!(cache.max_count(), 3);
+    cache.check_invariants().unwrap();
+    for n in 0..500 {
+        cache.put(n % 12, n);
+        cache.get(&(n % 3));
+        cache.put_prio_n(100 + n % 5, n, 7);
+        for (_, _, count, _) in cache.iter() {
+            assert!(count <= 3);
+        }
+        cache.check_invariants().unwrap();
+    }
+    assert_eq!(cache.count_of(&0), Some(3));
+}
+
+#[test]
+fn export_import_state() {
+    let mut cache = FbrCache::<u32, String, 3>::with_age_threshold(10, 4);
+    cache.set_evict_policy(EvictPolicy::Lru);
+    cache.set_max_count(5);
+    for n in 0..40 {
+        cache.put(n % 13, n.to_string());
+        cache.get(&(n % 4));
+    }
+    let state = cache.export_state();
+    assert_eq!(state.capacity, 10);
+    assert_eq!(state.entries.len(), 10);
+
+    let imported = FbrCache::<u32, String, 3>::import_state(state.clone());
+    assert_eq!(
+        imported.iter().collect::<Vec<_>>(),
+        cache.iter().collect::<Vec<_>>()
+    );
+    assert_eq!(imported.evict_policy(), EvictPolicy::Lru);
+    assert_eq!(imported.max_count(), 5);
+    assert_eq!(imported.export_state(), state);
+    imported.check_invariants().unwrap();
+}
+
+#[test]
+fn export_import_settings() {
+    let mut cache = FbrCache::<u32, u32, 8>::with_age_threshold(10, 4);
+    cache.set_scan_limit(2);
+    cache.set_hard_capacity(13);
+    cache.set_grow_on_demand(40);
+    cache.set_auto_age_threshold(Some(25));
+    cache.set_freq_decay_on_miss(2);
+    cache.set_region_aging(Region::Middle, 0);
+    cache.set_tombstones(5);
+    let state = cache.export_state();
+    assert_eq!(state.scan_limit, 2);
+    assert_eq!(state.slack, 3);
+    assert_eq!(state.grow_on_demand, Some(40));
+    assert_eq!(state.auto_age_threshold, Some(25));
+    assert_eq!(state.freq_decay_on_miss, 2);
+    assert_eq!(state.region_aging, [1, 0, 1]);
+    assert_eq!(state.tombstones, 5);
+
+    let imported = FbrCache::<u32, u32, 8>::import_state(state.clone());
+    assert_eq!(imported.hard_capacity(), 13);
+    assert_eq!(imported.region_aging(Region::Middle), 0);
+    assert_eq!(imported.export_state(), state);
+}
+
+#[test]
+fn into_parts() {
+    let mut cache = FbrCache::<u32, String, 3>::with_age_threshold(10, 4);
+    for n in 0..40 {
+        cache.put(n % 13, n.to_string());
+        cache.get(&(n % 4));
+    }
+    let expected = cache
+        .iter()
+        .map(|(k, v, c, r)| (*k, v.clone(), c, r))
+        .collect::<Vec<_>>();
+    let age_threshold = cache.effective_age_threshold();
+
+    let (entries, config) = cache.into_parts();
+    assert_eq!(entries, expected);
+    assert_eq!(
+        (
+            config.capacity,
+            config.age_threshold,
+            config.mid,
+            config.old
+        ),
+        (10, age_threshold, 3, 7)
+    );
+
+    let state = crate::CacheState {
+        capacity: config.capacity,
+        age_threshold: config.age_threshold,
+        max_count: usize::MAX,
+        evict_policy: EvictPolicy::default(),
+        scan_limit: usize::MAX,
+        promotion_policy: PromotionPolicy::default(),
+        overwrite_on_put: false,
+        slack: 0,
+        grow_on_demand: None,
+        auto_age_threshold: None,
+        freq_decay_on_miss: 0,
+        region_aging: [1; 3],
+        tombstones: 0,
+        entries: entries.into_iter().map(|(k, v, c, _)| (k, v, c)).collect(),
+    };
+    let warmed = FbrCache::<u32, String, 3>::import_state(state);
+    assert_eq!(
+        warmed
+            .iter()
+            .map(|(k, v, c, r)| (*k, v.clone(), c, r))
+            .collect::<Vec<_>>(),
+        expected
+    );
+    warmed.check_invariants().unwrap();
+}
+
+#[test]
+fn map_values() {
+    let mut cache = FbrCache::<u32, String, 3>::with_age_threshold(10, 4);
+    for n in 0..40 {
+        cache.put(n % 13, n.to_string());
+        cache.get(&(n % 4));
+    }
+    let pinned = cache.lru_order()[3];
+    assert!(cache.pin(&pinned));
+    let mapped = cache.map_values(|v| v.parse::<u32>().unwrap() * 2);
+    assert_eq!(
+        mapped
+            .iter()
+            .map(|(k, v, c, r)| (*k, *v, c, r))
+            .collect::<Vec<_>>(),
+        cache
+            .iter()
+            .map(|(k, v, c, r)| (*k, v.parse::<u32>().unwrap() * 2, c, r))
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(mapped.capacity(), 10);
+    assert_eq!(mapped.total_count, cache.total_count);
+    assert!(mapped.is_pinned(&pinned));
+    mapped.check_invariants().unwrap();
+}
+
+#[test]
+fn demote() {
+    let mut cache = FbrCache::<u32, String, 3>::with_age_threshold(10, 4);
+    cache.put_prio(0, s("0"));
+    for i in 1..10 {
+        cache.put(i, i.to_string());
+    }
+    let demoted = cache.demote(3);
+    assert_eq!(
+        demoted,
+        vec![(1, s("1"), 0), (2, s("2"), 0), (3, s("3"), 0)]
+    );
+    assert_eq!(cache.len(), 7);
+    assert_eq!(cache.evictions(), 0);
+    cache.check_invariants().unwrap();
+
+    let demoted = cache.demote_weighted(3, |_, v| v.len() + 1);
+    assert_eq!(demoted.len(), 2);
+    assert_eq!(cache.len(), 5);
+    assert_eq!(cache.demote(10).len(), 5);
+    assert!(cache.is_empty());
+    cache.check_invariants().unwrap();
+}
+
+#[test]
+fn overwrite_on_put() {
+    let mut cache = FbrCache::<u32, String, 3>::with_age_threshold(5, 4);
+    assert!(!cache.overwrite_on_put());
+    for i in 0..5 {
+        cache.put(i, i.to_string());
+    }
+    cache.put(0, s("zero"));
+    assert_eq!(cache.peek(&0), Some(&s("0")));
+    assert_eq!(cache.lru_order()[0], 0);
+    assert_eq!(cache.count_of(&0), Some(1));
+
+    cache.set_overwrite_on_put(true);
+    cache.put(1, s("one"));
+    assert_eq!(cache.peek(&1), Some(&s("one")));
+    assert_eq!(cache.lru_order()[0], 1);
+    assert_eq!(cache.count_of(&1), Some(1));
+    cache.check_invariants().unwrap();
+}
+
+#[test]
+fn try_reserve() {
+    let mut cache = FbrCache::<u32, String, 8>::try_new(100).unwrap();
+    assert!(cache.hash.capacity() >= 100);
+    cache.try_reserve(200).unwrap();
+    assert!(cache.hash.capacity() >= 200);
+    assert_eq!(cache.capacity(), 100);
+    assert!(cache.try_reserve(usize::MAX).is_err());
+    cache.put(1, s("1"));
+    cache.check_invariants().unwrap();
+}
+
+#[test]
+fn frequency_quantile() {
+    let mut cache = FbrCache::<u32, u32, 3>::with_age_threshold(20, 100);
+    assert_eq!(cache.frequency_quantile(0.5), 0);
+    for n in 0..400u32 {
+        cache.put(n % 23, n);
+        cache.get(&(n % 5));
+        cache.get(&(n % 7));
+    }
+    let mut counts = cache.iter().map(|(_, _, c, _)| c).collect::<Vec<_>>();
+    counts.sort_unstable();
+    assert!(counts.last().unwrap() >= &3);
+    for q in [0.0, 0.1, 0.25, 0.5, 0.75, 0.9, 0.99, 1.0] {
+        let rank = ((q * counts.len() as f64).ceil() as usize).max(1);
+        assert_eq!(cache.frequency_quantile(q), counts[rank - 1], "q = {}", q);
+    }
+}
+
+#[test]
+#[should_panic]
+fn frequency_quantile_invalid() {
+    FbrCache::<u32, u32, 3>::with_age_threshold(20, 100).frequency_quantile(1.5);
+}
+
+#[test]
+fn replace_with() {
+    static DROPS: AtomicUsize = AtomicUsize::new(0);
+
+    #[derive(Debug, PartialEq)]
+    struct Val(u32);
+    impl Drop for Val {
+        fn drop(&mut self) {
+            DROPS.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    let mut cache = FbrCache::<u32, Val, 3>::with_age_threshold(10, 4);
+    for i in 0..10 {
+        cache.put(i, Val(i));
+    }
+    cache.get(&9);
+    let before = cache
+        .iter()
+        .map(|(k, _, c, r)| (*k, c, r))
+        .collect::<Vec<_>>();
+    assert!(cache.replace_with(&9, |Val(v)| Val(v * 10)));
+    assert!(cache.replace_with(&0, |Val(v)| Val(v + 100)));
+    assert!(!cache.replace_with(&42, |v| v));
+    assert_eq!(cache.peek(&9), Some(&Val(90)));
+    assert_eq!(cache.peek(&0), Some(&Val(100)));
+    let after = cache
+        .iter()
+        .map(|(k, _, c, r)| (*k, c, r))
+        .collect::<Vec<_>>();
+    assert_eq!(before, after);
+    cache.check_invariants().unwrap();
+
+    // a panicking closure drops the moved value exactly once and removes the item
+    let drops = DROPS.load(Ordering::Relaxed);
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        cache.replace_with(&5, |_v| panic!("boom"))
+    }));
+    assert!(result.is_err());
+    assert_eq!(DROPS.load(Ordering::Relaxed), drops + 1);
+    assert!(!cache.contains_key(&5));
+    assert_eq!(cache.len(), 9);
+    cache.check_invariants().unwrap();
+}
+
+#[test]
+fn set_value() {
+    let mut cache = FbrCache::<u32, String, 3>::with_age_threshold(5, 4);
+    for i in 0..5 {
+        cache.put(i, i.to_string());
+    }
+    let before = cache
+        .iter()
+        .map(|(k, _, c, r)| (*k, c, r))
+        .collect::<Vec<_>>();
+    assert_eq!(cache.set_value(&4, s("four")), Ok(s("4")));
+    assert_eq!(cache.set_value(&7, s("seven")), Err(s("seven")));
+    assert_eq!(cache.peek(&4), Some(&s("four")));
+    assert!(!cache.contains_key(&7));
+    assert_eq!(
+        cache
+            .iter()
+            .map(|(k, _, c, r)| (*k, c, r))
+            .collect::<Vec<_>>(),
+        before
+    );
+}
+
+#[test]
+fn split_off_region() {
+    let mut cache = FbrCache::<u32, String, 3>::with_age_threshold(20, 4);
+    for n in 0..60 {
+        cache.put(n % 25, n.to_string());
+        cache.get(&(n % 6));
+    }
+    let mut original = cache
+        .iter()
+        .map(|(k, v, c, _)| (*k, v.clone(), c))
+        .collect::<Vec<_>>();
+    let old = cache
+        .iter()
+        .filter(|(.., r)| *r == Region::Old)
+        .map(|(k, v, c, _)| (*k, v.clone(), c))
+        .collect::<Vec<_>>();
+    assert_eq!(old.len(), 5);
+
+    let split = cache.split_off_region(Region::Old);
+    assert_eq!(split.capacity(), 5);
+    assert_eq!(
+        split
+            .iter()
+            .map(|(k, v, c, _)| (*k, v.clone(), c))
+            .collect::<Vec<_>>(),
+        old
+    );
+    assert_eq!(cache.len(), 15);
+    cache.check_invariants().unwrap();
+    split.check_invariants().unwrap();
+
+    let mut combined = cache
+        .iter()
+        .chain(split.iter())
+        .map(|(k, v, c, _)| (*k, v.clone(), c))
+        .collect::<Vec<_>>();
+    combined.sort();
+    original.sort();
+    assert_eq!(combined, original);
+}
+
+#[test]
+fn recommend_capacity() {
+    let mut cache = FbrCache::<u32, u32, 8>::with_age_threshold(40, 100);
+    assert_eq!(cache.recommend_capacity(0.5), 40);
+    // hot set of 10 keys, every third lookup is for a key never seen again
+    let mut cold = 1000;
+    for n in 0..3000 {
+        let key = if n % 3 == 2 {
+            cold += 1;
+            cold
+        } else {
+            n % 10
+        };
+        if cache.get(&key).is_none() {
+            cache.put(key, n);
+        }
+    }
+    assert_eq!(cache.hits() + cache.misses(), 3000);
+    let hit_rate = cache.hits() as f64 / 3000.0;
+    assert!(hit_rate > 0.6 && hit_rate < 0.67, "{}", hit_rate);
+
+    let shrink = cache.recommend_capacity(hit_rate * 0.95);
+    assert!((8..=15).contains(&shrink), "{}", shrink);
+    assert_eq!(cache.recommend_capacity(0.0), 0);
+    assert!(cache.recommend_capacity(0.9) > 40);
+
+    cache.reset_stats();
+    assert_eq!(cache.hits(), 0);
+    assert_eq!(cache.misses(), 0);
+
+    // only misses: nothing to extrapolate from
+    for key in 5000..5010 {
+        cache.get(&key);
+    }
+    assert_eq!(cache.misses(), 10);
+    assert_eq!(cache.recommend_capacity(0.0), 40);
+    assert_eq!(cache.recommend_capacity(0.5), 40);
+    assert_eq!(cache.recommend_capacity(1.0), 40);
+}
+
+#[test]
+fn iter_with_rank() {
+    let mut cache = FbrCache::<u32, String, 3>::with_age_threshold(10, 4);
+    for n in 0..25 {
+        cache.put(n % 13, n.to_string());
+    }
+    let ranked = cache.iter_with_rank().collect::<Vec<_>>();
+    assert_eq!(ranked.len(), 10);
+    for (idx, ((rank, k, v, c, r), item)) in ranked.iter().zip(cache.iter()).enumerate() {
+        assert_eq!(*rank, idx);
+        assert_eq!((*k, *v, *c, *r), item);
+        assert_eq!(cache.recency_rank(k), Some(idx));
+    }
+}
+
+#[test]
+fn clear_except_top() {
+    let mut cache = FbrCache::<u32, String, 8>::with_age_threshold(10, 100);
+    for i in 0..10 {
+        cache.put(i, i.to_string());
+    }
+    for _ in 0..3 {
+        for i in 0..10 {
+            cache.put(i, i.to_string());
+        }
+    }
+    for i in 0..10 {
+        cache.put(i + 10, i.to_string());
+        cache.get(&3);
+        cache.get(&6);
+    }
+    let (c3, c6) = (cache.count_of(&3).unwrap(), cache.count_of(&6).unwrap());
+    assert!(cache
+        .iter()
+        .all(|(k, _, c, _)| *k == 3 || *k == 6 || c < c3.min(c6)));
+
+    assert_eq!(cache.clear_except_top(2), 8);
+    assert_eq!(cache.len(), 2);
+    assert_eq!(cache.count_of(&3), Some(c3));
+    assert_eq!(cache.count_of(&6), Some(c6));
+    assert_eq!(cache.total_count, c3 + c6);
+    assert_eq!(cache.clear_except_top(5), 0);
+    cache.check_invariants().unwrap();
+}
+
+#[test]
+fn region_at_rank() {
+    assert_eq!(Region::ALL.len(), 3);
+    let mut region = Some(Region::New);
+    for r in Region::ALL {
+        assert_eq!(region, Some(r));
+        region = r.next();
+    }
+    assert_eq!(region, None);
+
+    let mut cache = FbrCache::<u32, String, 3>::with_age_threshold(10, 4);
+    for n in 0..15 {
+        cache.put(n, n.to_string());
+        for (rank, .., r) in cache.iter_with_rank() {
+            assert_eq!(cache.region_at_rank(rank), Some(r));
+        }
+        assert_eq!(cache.region_at_rank(cache.len()), None);
+    }
+    cache.remove(&14);
+    cache.remove(&7);
+    for (rank, .., r) in cache.iter_with_rank() {
+        assert_eq!(cache.region_at_rank(rank), Some(r));
+    }
+}
+
+#[test]
+fn evict_overflow_counts() {
+    let mut cache = FbrCache::<u32, String, 3>::with_age_threshold(8, 1000);
+    let mut state = cache.export_state();
+    state.entries = [0, 0, 1, 2, 0, 7, 4, 5]
+        .into_iter()
+        .enumerate()
+        .map(|(k, c)| (k as u32, k.to_string(), c))
+        .collect();
+    cache = FbrCache::import_state(state);
+    cache.check_invariants().unwrap();
+    assert_eq!(
+        cache
+            .iter()
+            .filter(|(.., r)| *r == Region::Old)
+            .map(|(k, _, c, _)| (*k, c))
+            .collect::<Vec<_>>(),
+        vec![(6, 4), (7, 5)]
+    );
+
+    // the least-used “old” item goes first, even though it is not the least recent
+    cache.put(10, s("10"));
+    assert!(!cache.contains_key(&6));
+    assert!(cache.contains_key(&7));
+    // now 5 has moved into the old region, with a count of 7 it is hotter than 7
+    cache.put(11, s("11"));
+    assert!(!cache.contains_key(&7));
+    assert!(cache.contains_key(&5));
+    cache.check_invariants().unwrap();
+}
+
+#[test]
+fn on_insert() {
+    use std::sync::{Arc, Mutex};
+
+    let mut cache = FbrCache::<u32, String, 3>::with_age_threshold(5, 4);
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let log = seen.clone();
+    cache.on_insert(move |k, v, prio| log.lock().unwrap().push((*k, v.clone(), prio)));
+    cache.set_admission(|k| *k != 13);
+
+    let mut expected = Vec::new();
+    for n in 0..30 {
+        let key = n % 8 + 10;
+        let present = cache.contains_key(&key);
+        if n % 3 == 0 {
+            cache.put_prio(key, n.to_string());
+        } else {
+            cache.put(key, n.to_string());
+        }
+        if !present && key != 13 {
+            expected.push((key, n.to_string(), n % 3 == 0));
+        }
+    }
+    cache.entry(42).or_insert_prio(s("42"));
+    expected.push((42, s("42"), true));
+    assert_eq!(*seen.lock().unwrap(), expected);
+}
+
+#[test]
+fn get_or_recompute() {
+    let mut cache = FbrCache::<u32, (u32, String), 3>::with_age_threshold(5, 4);
+    for i in 0..5 {
+        cache.put(i, (1, i.to_string()));
+    }
+
+    // fresh hit
+    let v = cache.get_or_recompute(0, |v| v.0 == 1, || unreachable!());
+    assert_eq!(v, &(1, s("0")));
+    assert_eq!(cache.count_of(&0), Some(1));
+    assert_eq!(cache.lru_order()[0], 0);
+
+    // stale hit keeps the frequency
+    let v = cache.get_or_recompute(0, |v| v.0 == 2, || (2, s("zero")));
+    assert_eq!(v, &(2, s("zero")));
+    assert_eq!(cache.count_of(&0), Some(1));
+    assert_eq!(cache.hits(), 2);
+
+    // miss
+    let v = cache.get_or_recompute(7, |_| unreachable!(), || (1, s("7")));
+    assert_eq!(v, &(1, s("7")));
+    assert_eq!(cache.count_of(&7), Some(0));
+    assert_eq!(cache.misses(), 1);
+    assert_eq!(cache.len(), 5);
+    cache.check_invariants().unwrap();
+}
+
+#[test]
+fn proportional_regions() {
+    let mut cache = FbrCache::<u32, String, 8>::with_age_threshold(20, 100);
+    for i in 0..10 {
+        cache.put(i, i.to_string());
+        cache.check_invariants().unwrap();
+    }
+    // half full: 3 new, 4 middle and 3 old items
+    let regions = cache.iter().map(|(.., r)| r).collect::<Vec<_>>();
+    assert_eq!(regions.iter().filter(|r| **r == Region::New).count(), 3);
+    assert_eq!(regions.iter().filter(|r| **r == Region::Middle).count(), 4);
+    assert_eq!(regions.iter().filter(|r| **r == Region::Old).count(), 3);
+
+    // counts accumulate while occupancy stays at 50%
+    for n in 0..50 {
+        cache.get(&(n % 10));
+        if n % 5 == 0 {
+            cache.remove(&(n % 10));
+            cache.put(n % 10, n.to_string());
+        }
+        cache.check_invariants().unwrap();
+    }
+    assert_eq!(cache.len(), 10);
+    assert!(cache.iter().any(|(.., c, _)| c > 1));
+    assert!(cache.frequency_sum(Region::Middle) + cache.frequency_sum(Region::Old) > 0);
+}
+
+#[test]
+fn hard_capacity() {
+    let mut cache = FbrCache::<u32, String, 3>::with_age_threshold(10, 4);
+    assert_eq!(cache.hard_capacity(), 10);
+    cache.set_hard_capacity(15);
+    assert_eq!(cache.hard_capacity(), 15);
+    assert_eq!(cache.capacity(), 10);
+
+    for i in 0..15 {
+        cache.put(i, i.to_string());
+        assert_eq!(cache.len(), i as usize + 1);
+        cache.check_invariants().unwrap();
+    }
+    assert_eq!(cache.evictions(), 0);
+    cache.put(15, s("15"));
+    assert_eq!(cache.len(), 10);
+    assert_eq!(cache.evictions(), 6);
+    assert!(cache.contains_key(&15));
+    cache.check_invariants().unwrap();
+
+    for i in 16..21 {
+        cache.put(i, i.to_string());
+    }
+    assert_eq!(cache.len(), 15);
+    assert_eq!(cache.evictions(), 6);
+
+    cache.set_hard_capacity(0);
+    assert_eq!(cache.hard_capacity(), 10);
+    cache.put(21, s("21"));
+    assert_eq!(cache.len(), 10);
+    assert_eq!(cache.evictions(), 12);
+    cache.put(22, s("22"));
+    assert_eq!(cache.len(), 10);
+    assert_eq!(cache.evictions(), 13);
+    cache.check_invariants().unwrap();
+}
+
+#[test]
+fn entry_and_modify() {
+    let mut cache = FbrCache::<u32, u32, 3>::with_age_threshold(5, 4);
+    for _ in 0..5 {
+        cache.entry(7).and_modify(|v| *v += 1).or_insert(0);
+    }
+    assert_eq!(cache.peek(&7), Some(&4));
+    assert_eq!(cache.hits(), 4);
+    assert_eq!(cache.misses(), 1);
+    assert!(matches!(
+        cache.entry(8).and_modify(|_| unreachable!()),
+        crate::Entry::Vacant(_)
+    ));
+    cache.check_invariants().unwrap();
+}
+
+#[test]
+fn or_insert_evicting() {
+    use crate::Entry;
+
+    let mut cache = FbrCache::<u32, String, 3>::with_age_threshold(5, 4);
+    for i in 0..4 {
+        cache.put(i, i.to_string());
+    }
+    let Entry::Vacant(e) = cache.entry(4) else {
+        panic!("occupied")
+    };
+    assert_eq!(e.or_insert_evicting(s("4")), (&mut s("4"), None));
+
+    cache.get(&0);
+    let victim = *cache.lru_order().last().unwrap();
+    let Entry::Vacant(e) = cache.entry(5) else {
+        panic!("occupied")
+    };
+    let (value, evicted) = e.or_insert_evicting(s("5"));
+    value.push('!');
+    assert_eq!(evicted, Some((victim, victim.to_string())));
+    assert_eq!(cache.peek(&5), Some(&s("5!")));
+    assert!(!cache.contains_key(&victim));
+    assert_eq!(cache.len(), 5);
+    assert_eq!(cache.evictions(), 1);
+    cache.check_invariants().unwrap();
+}
+
+#[test]
+fn pin() {
+    let mut cache = FbrCache::<u32, String, 3>::with_age_threshold(5, 4);
+    for i in 0..5 {
+        assert!(cache.put(i, i.to_string()));
+    }
+    assert!(cache.pin(&0));
+    assert!(cache.pin(&1));
+    assert!(!cache.pin(&42));
+    assert!(cache.is_pinned(&0));
+    for i in 10..30 {
+        assert!(cache.put(i, i.to_string()));
+        cache.check_invariants().unwrap();
+    }
+    assert!(cache.contains_key(&0));
+    assert!(cache.contains_key(&1));
+    assert_eq!(cache.len(), 5);
+
+    for i in 27..30 {
+        assert!(cache.pin(&i));
+    }
+    assert!(!cache.put(30, s("30")));
+    assert!(!cache.put_prio(30, s("30")));
+    assert!(!cache.put_if_absent(30, s("30")));
+    assert!(!cache.contains_key(&30));
+    assert_eq!(cache.prune_to(0), 0);
+    assert!(cache.demote(1).is_empty());
+    // keys already present are still accepted
+    assert!(cache.put(0, s("zero")));
+
+    // must insert, hence going over capacity
+    cache.get_or_default_mut(31);
+    assert_eq!(cache.len(), 6);
+    cache.check_invariants().unwrap();
+
+    assert!(cache.unpin(&28));
+    assert!(!cache.is_pinned(&28));
+    assert!(cache.put(32, s("32")));
+    assert_eq!(cache.len(), 5);
+    assert!(!cache.contains_key(&28));
+    assert!(!cache.contains_key(&31));
+    assert!(cache.remove(&0).is_some());
+    assert_eq!(cache.pinned, 3);
+    cache.check_invariants().unwrap();
+}
+
+#[test]
+fn auto_age_threshold() {
+    let mut cache = FbrCache::<u32, u32, 8>::with_age_threshold(20, 1);
+    for i in 0..20 {
+        cache.put(i, i);
+    }
+    cache.set_auto_age_threshold(Some(200));
+    let mut intervals = Vec::new();
+    let mut last = (cache.age_events(), cache.hit_count);
+    let mut phase = |cache: &mut FbrCache<u32, u32, 8>, rounds: u32, repeat: u32| {
+        let mut seen = Vec::new();
+        for n in 0..rounds {
+            // every key hit `repeat` times in a row, only the first hit counts
+            for _ in 0..repeat {
+                cache.get(&(n % 20));
+            }
+            if cache.age_events() > last.0 {
+                seen.push(cache.hit_count - last.1);
+                last = (cache.age_events(), cache.hit_count);
+            }
+        }
+        seen
+    };
+    intervals.push(phase(&mut cache, 5000, 1));
+    intervals.push(phase(&mut cache, 5000, 4));
+    for seen in intervals {
+        assert!(seen.len() > 10, "{:?}", seen);
+        for interval in &seen[seen.len() - 5..] {
+            assert!((190..=210).contains(interval), "{:?}", seen);
+        }
+    }
+    cache.set_auto_age_threshold(None);
+    let threshold = cache.effective_age_threshold();
+    for n in 0..1000 {
+        cache.get(&(n % 20));
+    }
+    assert_eq!(cache.effective_age_threshold(), threshold);
+}
+
+#[test]
+fn to_dot() {
+    let mut cache = FbrCache::<&str, u32, 2>::with_age_threshold(4, 100);
+    for (i, k) in ["a", "b", "c", "d"].into_iter().enumerate() {
+        cache.put(k, i as u32);
+    }
+    cache.get(&"a");
+    cache.get(&"b");
+    // order b a d c with counts 1 1 0 0, regions New Middle Middle Old
+    let dot = cache.to_dot();
+    assert!(dot.starts_with("digraph fbr_cache {"));
+    for line in [
+        "n0 [label=\"\\\"b\\\" (1)\"];",
+        "n3 [label=\"\\\"c\\\" (0)\"];",
+        "label=\"Middle\";",
+        "n0 -> n1;",
+        "n2 -> n3;",
+        "c0 -> n2 [style=dashed];",
+        "n2 -> n3 [style=dashed];",
+        "c1 -> n0 [style=dashed];",
+        "n0 -> n1 [style=dashed];",
+        "overflow [shape=ellipse];",
+        "mid -> n1 [style=dotted];",
+        "old -> n3 [style=dotted];",
+    ] {
+        assert!(dot.contains(line), "{} not in\n{}", line, dot);
+    }
+    assert!(!dot.contains("n3 -> n4"));
+    let clusters = dot
+        .split("subgraph")
+        .skip(1)
+        .map(|c| c.split('}').next().unwrap())
+        .collect::<Vec<_>>();
+    assert!(clusters[0].contains("n0 [") && !clusters[0].contains("n1 ["));
+    assert!(clusters[2].contains("n3 [") && !clusters[2].contains("n2 ["));
+}
+
+#[test]
+fn retain_regions() {
+    let mut cache = FbrCache::<u32, String, 3>::with_age_threshold(20, 4);
+    for n in 0..50 {
+        cache.put(n % 23, n.to_string());
+        cache.get(&(n % 4));
+    }
+    let before = cache
+        .iter()
+        .map(|(k, _, c, r)| (*k, c, r))
+        .collect::<Vec<_>>();
+    let total = cache.total_count;
+    cache.retain_regions(&[Region::New, Region::Middle]);
+    let kept = before
+        .iter()
+        .filter(|(.., r)| *r != Region::Old)
+        .map(|(k, c, _)| (*k, *c))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        cache.iter().map(|(k, _, c, _)| (*k, c)).collect::<Vec<_>>(),
+        kept
+    );
+    let dropped = before
+        .iter()
+        .filter(|(.., r)| *r == Region::Old)
+        .map(|(_, c, _)| *c)
+        .sum::<usize>();
+    assert_eq!(cache.total_count, total - dropped);
+    assert_eq!(cache.region_at_rank(cache.len() - 1), Some(Region::Old));
+    cache.check_invariants().unwrap();
+
+    cache.retain_regions(&[Region::New]);
+    assert_eq!(cache.len(), 4);
+    cache.retain_regions(&[]);
+    assert!(cache.is_empty());
+    cache.check_invariants().unwrap();
+}
+
+#[test]
+#[cfg(debug_assertions)]
+fn explain_eviction() {
+    use crate::EvictReason;
+
+    let mut cache = FbrCache::<u32, String, 3>::with_age_threshold(10, 4);
+    assert_eq!(cache.explain_eviction(), None);
+    for i in 0..10 {
+        cache.put(i, i.to_string());
+    }
+    // move the two oldest items out of the old region and back with a count of one
+    for i in [1, 0] {
+        cache.get(&i);
+    }
+    for i in 2..5 {
+        cache.get(&i);
+    }
+    let explanation = cache.explain_eviction().unwrap();
+    assert_eq!(explanation.reason, EvictReason::OldChain(0));
+    assert_eq!(explanation.region, Region::Old);
+    let (key, _, count) = cache.demote(1).pop().unwrap();
+    assert_eq!((explanation.key, explanation.count), (key, count));
+
+    cache.set_evict_policy(EvictPolicy::LruBelow(1));
+    let explanation = cache.explain_eviction().unwrap();
+    assert_eq!(explanation.reason, EvictReason::OldBelowLimit);
+    assert_eq!(explanation.count, 0);
+    assert_eq!(cache.demote(1)[0].0, explanation.key);
+
+    cache.set_evict_policy(EvictPolicy::Lru);
+    let explanation = cache.explain_eviction().unwrap();
+    assert_eq!(explanation.reason, EvictReason::LruFallback);
+    assert_eq!(Some(&explanation.key), cache.lru_order().last());
+    assert_eq!(cache.demote(1)[0].0, explanation.key);
+    cache.check_invariants().unwrap();
+}
+
+#[test]
+fn reset_counts() {
+    let mut cache = FbrCache::<u32, String, 3>::with_age_threshold(10, 4);
+    for n in 0..40 {
+        cache.put(n % 13, n.to_string());
+        cache.get(&(n % 3));
+    }
+    let before = cache
+        .iter()
+        .map(|(k, v, _, r)| (*k, v.clone(), r))
+        .collect::<Vec<_>>();
+    assert!(cache.iter().any(|(_, _, c, _)| c > 0));
+
+    cache.reset_counts();
+    assert_eq!(
+        cache
+            .iter()
+            .map(|(k, v, _, r)| (*k, v.clone(), r))
+            .collect::<Vec<_>>(),
+        before
+    );
+    assert!(cache.iter().all(|(_, _, c, _)| c == 0));
+    assert_eq!(cache.total_count, 0);
+    assert_eq!(cache.len(), 10);
+    cache.check_invariants().unwrap();
+}
+
+#[test]
+fn on_resize() {
+    use std::sync::{Arc, Mutex};
+
+    let mut cache = FbrCache::<u32, u32, 3>::with_age_threshold(4, 4);
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let log = seen.clone();
+    cache.on_resize(move |old, new| log.lock().unwrap().push((old, new)));
+    cache.set_grow_on_demand(8);
+
+    for k in 0..5 {
+        cache.put(k, k);
+    }
+    assert_eq!(cache.capacity(), 4);
+    // the evicted key comes back, so the cache doubles
+    cache.put(0, 0);
+    assert_eq!(cache.capacity(), 8);
+    assert_eq!(cache.len(), 5);
+
+    cache.set_capacity(3);
+    assert_eq!(cache.len(), 3);
+    cache.set_capacity(3);
+    cache.set_capacity(6);
+    assert_eq!(*seen.lock().unwrap(), vec![(4, 8), (8, 3), (3, 6)]);
+    assert_eq!(cache.effective_age_threshold(), 24);
+    cache.check_invariants().unwrap();
+}
+
+#[test]
+fn iter_by_importance() {
+    let mut cache = FbrCache::<u32, String, 3>::with_age_threshold(10, 4);
+    for n in 0..40 {
+        cache.put(n % 13, n.to_string());
+        cache.get(&(n % 4));
+    }
+    let keys = |weight| {
+        cache
+            .iter_by_importance(weight)
+            .into_iter()
+            .map(|(k, _)| *k)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        keys(1.0),
+        cache.hot_keys(10).into_iter().copied().collect::<Vec<_>>()
+    );
+    assert_eq!(keys(0.0), cache.lru_order());
+
+    let scores = cache.iter_by_importance(0.0);
+    assert_eq!(scores[0].1, 1.0);
+    assert_eq!(scores[9].1, 0.1);
+    assert!(FbrCache::<u32, String, 3>::with_age_threshold(10, 4)
+        .iter_by_importance(0.5)
+        .is_empty());
+}
+
+#[test]
+fn occupied_chains() {
+    let mut cache = FbrCache::<u32, u32, 70>::with_age_threshold(20, 40);
+    for n in 0..3000u32 {
+        // a few very hot keys reach the upper chains, the rest churns through chain 0
+        let key = if n % 3 == 0 { n % 5 } else { n % 41 + 10 };
+        if cache.get(&key).is_none() {
+            cache.put(key, n);
+        }
+        cache.check_invariants().unwrap();
+    }
+    assert!(cache.age_events() > 0);
+    assert!(cache.iter().any(|(_, _, c, _)| c >= 64));
+    cache.set_max_count(30);
+    cache.check_invariants().unwrap();
+    cache.reset_counts();
+    cache.check_invariants().unwrap();
+    cache.retain_regions(&[Region::Old]);
+    cache.check_invariants().unwrap();
+    cache.clear();
+    cache.check_invariants().unwrap();
+}
+
+#[test]
+fn promotions_and_demotions() {
+    let mut cache = FbrCache::<u32, String, 3>::with_age_threshold(10, 4);
+    for i in 0..10 {
+        cache.put(i, i.to_string());
+    }
+    assert_eq!(cache.promotions(), 0);
+    assert!(cache.demotions() > 0);
+    cache.reset_stats();
+
+    // in a cyclic scan every hit promotes an old item and pushes another one into the old region
+    for round in 0..3 {
+        for i in 0..10 {
+            cache.get(&i);
+        }
+        assert_eq!(cache.promotions(), 10 * (round + 1));
+        assert_eq!(cache.demotions(), 10 * (round + 1));
+    }
+
+    // a stable hot set in the new region causes no transitions
+    cache.reset_stats();
+    for _ in 0..10 {
+        cache.get(&9);
+        cache.get(&8);
+    }
+    assert_eq!((cache.promotions(), cache.demotions()), (0, 0));
+}
+
+#[test]
+fn get_unchecked() {
+    let mut cache = FbrCache::<u32, String, 3>::with_age_threshold(5, 4);
+    for i in 0..5 {
+        cache.put(i, i.to_string());
+    }
+    let handle = cache.handle(&0).unwrap();
+    assert!(cache.handle(&7).is_none());
+    assert_eq!(cache.lru_order()[4], 0);
+
+    assert_eq!(unsafe { cache.get_unchecked(&handle) }, "0");
+    assert_eq!(cache.lru_order()[0], 0);
+    assert_eq!(cache.count_of(&0), Some(1));
+    assert_eq!(cache.hits(), 1);
+
+    // the handle survives other operations that keep the item
+    cache.get(&3);
+    cache.put(5, s("5"));
+    assert_eq!(unsafe { cache.get_unchecked(&handle) }, "0");
+    assert_eq!(cache.lru_order()[0], 0);
+    cache.check_invariants().unwrap();
+}
+
+#[test]
+fn clear_drop_order() {
+    use std::sync::{Arc, Mutex};
+
+    struct Recorder(u32, Arc<Mutex<Vec<u32>>>);
+    impl Drop for Recorder {
+        fn drop(&mut self) {
+            self.1.lock().unwrap().push(self.0);
+        }
+    }
+
+    let dropped = Arc::new(Mutex::new(Vec::new()));
+    let mut cache = FbrCache::<u32, Recorder, 3>::with_age_threshold(10, 4);
+    for i in 0..8 {
+        cache.put(i, Recorder(i, dropped.clone()));
+    }
+    cache.get(&2);
+    cache.get(&5);
+    let mut expected = cache.lru_order();
+    expected.reverse();
+    cache.clear();
+    assert_eq!(*dropped.lock().unwrap(), expected);
+    assert!(cache.is_empty());
+    cache.check_invariants().unwrap();
+
+    dropped.lock().unwrap().clear();
+    for i in 0..4 {
+        cache.put(i, Recorder(i, dropped.clone()));
+    }
+    drop(cache);
+    assert_eq!(*dropped.lock().unwrap(), vec![0, 1, 2, 3]);
+}
+
+#[test]
+fn scan_limit() {
+    let state = |counts: &[usize]| crate::CacheState {
+        capacity: counts.len(),
+        age_threshold: 1000,
+        max_count: usize::MAX,
+        evict_policy: EvictPolicy::default(),
+        scan_limit: usize::MAX,
+        promotion_policy: PromotionPolicy::default(),
+        overwrite_on_put: false,
+        slack: 0,
+        grow_on_demand: None,
+        auto_age_threshold: None,
+        freq_decay_on_miss: 0,
+        region_aging: [1; 3],
+        tombstones: 0,
+        entries: counts
+            .iter()
+            .enumerate()
+            .map(|(k, c)| (k as u32, k.to_string(), *c))
+            .collect(),
+    };
+    // only the old region (the last three items) has non-zero counts
+    let mut cache =
+        FbrCache::<u32, String, 8>::import_state(state(&[0, 0, 0, 0, 0, 0, 0, 2, 1, 2]));
+    assert_eq!(cache.scan_limit(), usize::MAX);
+    assert_eq!(cache.demote(1)[0].0, 8);
+
+    let mut cache =
+        FbrCache::<u32, String, 8>::import_state(state(&[0, 0, 0, 0, 0, 0, 0, 2, 1, 2]));
+    cache.set_scan_limit(1);
+    // chain 0 holds no old item, so the least recently used item is taken
+    assert_eq!(cache.region_of(&9), Some(Region::Old));
+    assert_eq!(cache.demote(1)[0].0, 9);
+    // with one item less the old region starts at item 6, which is found in chain 0
+    assert_eq!(cache.demote(1)[0].0, 6);
+
+    cache.set_scan_limit(0);
+    cache.put(10, s("10"));
+    cache.put(11, s("11"));
+    for i in 12..30 {
+        // all chains are skipped, eviction degrades to plain LRU
+        let back = *cache.lru_order().last().unwrap();
+        assert!(cache.put(i, i.to_string()));
+        assert!(!cache.contains_key(&back));
+        assert_eq!(cache.len(), 10);
+    }
+    cache.check_invariants().unwrap();
+}
+
+#[test]
+fn freq_decay_on_miss() {
+    let run = |weight| {
+        let mut cache = FbrCache::<u32, String, 8>::with_age_threshold(10, 4);
+        cache.set_freq_decay_on_miss(weight);
+        for i in 0..10 {
+            cache.put(i, i.to_string());
+        }
+        // make keys 0 and 1 hot
+        for n in 0..12 {
+            cache.get(&(n % 2));
+            for i in 2..10 {
+                cache.get(&i);
+            }
+        }
+        cache.reset_stats();
+        let hot = cache.count_of(&0).unwrap() + cache.count_of(&1).unwrap();
+        // a miss storm for keys that are not cached
+        for n in 100..200 {
+            assert_eq!(cache.get(&n), None);
+        }
+        cache.check_invariants().unwrap();
+        (
+            hot,
+            cache.count_of(&0).unwrap() + cache.count_of(&1).unwrap(),
+            cache.age_events(),
+        )
+    };
+    assert_eq!(
+        FbrCache::<u32, String, 3>::with_age_threshold(10, 4).freq_decay_on_miss(),
+        0
+    );
+    let (hot, after, ages) = run(0);
+    assert_eq!((after, ages), (hot, 0));
+    let (hot, after, ages) = run(1);
+    assert!(ages > 0);
+    assert!(after < hot, "{} -> {}", hot, after);
+}
+
+#[test]
+fn rehome() {
+    let mut src = FbrCache::<u32, String, 8>::with_age_threshold(5, 4);
+    let mut dest = FbrCache::<u32, String, 8>::with_age_threshold(5, 4);
+    for i in 0..5 {
+        src.put(i, i.to_string());
+        dest.put(i + 10, (i + 10).to_string());
+    }
+    for _ in 0..3 {
+        src.get(&0);
+        src.get(&4);
+    }
+    let count = src.count_of(&0).unwrap();
+    assert!(count > 0);
+    let total = (src.total_count, dest.total_count);
+
+    assert!(src.rehome(&0, &mut dest));
+    assert!(!src.contains_key(&0));
+    assert_eq!(src.len(), 4);
+    // dest was full, so its coldest item made room
+    assert_eq!(dest.len(), 5);
+    assert!(!dest.contains_key(&10));
+    assert_eq!(dest.count_of(&0), Some(count));
+    assert_eq!(dest.lru_order()[0], 0);
+    assert_eq!(dest.peek(&0), Some(&s("0")));
+    assert_eq!(
+        (src.total_count, dest.total_count),
+        (total.0 - count, total.1 + count)
+    );
+
+    assert!(!src.rehome(&0, &mut dest));
+    dest.put(1, s("one"));
+    assert!(!src.rehome(&1, &mut dest));
+    assert_eq!(src.peek(&1), Some(&s("1")));
+    src.check_invariants().unwrap();
+    dest.check_invariants().unwrap();
+}
+
+#[test]
+fn region_aging() {
+    let state = |age_threshold| crate::CacheState {
+        capacity: 10,
+        age_threshold,
+        max_count: usize::MAX,
+        evict_policy: EvictPolicy::default(),
+        scan_limit: usize::MAX,
+        promotion_policy: PromotionPolicy::default(),
+        overwrite_on_put: false,
+        slack: 0,
+        grow_on_demand: None,
+        auto_age_threshold: None,
+        freq_decay_on_miss: 0,
+        region_aging: [1; 3],
+        tombstones: 0,
+        entries: [4, 4, 4, 2, 2, 2, 2, 8, 8, 8]
+            .into_iter()
+            .enumerate()
+            .map(|(k, c)| (k as u32, k.to_string(), c))
+            .collect(),
+    };
+    let counts =
+        |cache: &FbrCache<u32, String, 8>| cache.iter().map(|(_, _, c, _)| c).collect::<Vec<_>>();
+
+    let mut cache = FbrCache::<u32, String, 8>::import_state(state(43));
+    assert_eq!(cache.region_aging(Region::Old), 1);
+    cache.set_region_aging(Region::New, 0);
+    cache.set_region_aging(Region::Middle, 0);
+    // a hit in the new region triggers aging without changing any count
+    cache.get(&0);
+    assert_eq!(cache.age_events(), 1);
+    assert_eq!(counts(&cache), vec![4, 4, 4, 2, 2, 2, 2, 4, 4, 4]);
+    cache.check_invariants().unwrap();
+
+    // a stronger decay for the old region
+    let mut cache = FbrCache::<u32, String, 8>::import_state(state(43));
+    cache.set_region_aging(Region::New, 0);
+    cache.set_region_aging(Region::Middle, 0);
+    cache.set_region_aging(Region::Old, 3);
+    cache.get(&0);
+    assert_eq!(counts(&cache), vec![4, 4, 4, 2, 2, 2, 2, 1, 1, 1]);
+
+    // sparing the new and middle regions is not enough, so they are halved as well
+    let mut cache = FbrCache::<u32, String, 8>::import_state(state(30));
+    cache.set_region_aging(Region::New, 0);
+    cache.set_region_aging(Region::Middle, 0);
+    cache.get(&0);
+    assert_eq!(counts(&cache), vec![2, 2, 2, 1, 1, 1, 1, 4, 4, 4]);
+    assert_eq!(cache.age_events(), 1);
+    cache.check_invariants().unwrap();
+
+    // the default halves everything
+    let mut cache = FbrCache::<u32, String, 8>::import_state(state(43));
+    cache.get(&0);
+    assert_eq!(counts(&cache), vec![2, 2, 2, 1, 1, 1, 1, 4, 4, 4]);
+}
+
+#[test]
+fn get_cow() {
+    use std::sync::Arc;
+
+    struct Big(Vec<u32>, Arc<AtomicUsize>);
+    impl Clone for Big {
+        fn clone(&self) -> Self {
+            self.1.fetch_add(1, Ordering::Relaxed);
+            Big(self.0.clone(), self.1.clone())
+        }
+    }
+
+    let clones = Arc::new(AtomicUsize::new(0));
+    let mut cache = FbrCache::<u32, Big, 3>::with_age_threshold(5, 4);
+    cache.put(1, Big(vec![1, 2, 3], clones.clone()));
+
+    let value = cache.get_cow(&1).unwrap();
+    assert_eq!(value.0, vec![1, 2, 3]);
+    assert!(!value.is_modified());
+    drop(value);
+    assert_eq!(clones.load(Ordering::Relaxed), 0);
+    assert!(cache.get_cow(&2).is_none());
+
+    let mut value = cache.get_cow(&1).unwrap();
+    value.to_mut().0.push(4);
+    value.to_mut().0.push(5);
+    assert!(value.is_modified());
+    assert_eq!(value.0, vec![1, 2, 3, 4, 5]);
+    drop(value);
+    assert_eq!(clones.load(Ordering::Relaxed), 1);
+    assert_eq!(cache.peek(&1).unwrap().0, vec![1, 2, 3, 4, 5]);
+    assert_eq!(cache.hits(), 2);
+}
+
+#[test]
+fn chains_bytes() {
+    let head = 2 * std::mem::size_of::<usize>();
+    assert_eq!(FbrCache::<u32, String, 8>::chains_bytes(), 8 * head);
+    assert_eq!(FbrCache::<String, Vec<u8>, 64>::chains_bytes(), 64 * head);
+    assert!(
+        std::mem::size_of::<FbrCache<u32, u32, 64>>()
+            >= std::mem::size_of::<FbrCache<u32, u32, 8>>() + 56 * head
+    );
+}
+
+#[test]
+fn take_tombstones() {
+    let mut cache = FbrCache::new(10);
+    for i in 0..5 {
+        cache.put(i, i);
+    }
+    assert_eq!(cache.take(&0), Some(0));
+    assert_eq!(cache.status(&0), EntryStatus::Absent);
+    assert_eq!(cache.take(&0), None);
+
+    cache.set_tombstones(2);
+    assert_eq!(cache.take(&1), Some(1));
+    assert_eq!(cache.status(&1), EntryStatus::RecentlyRemoved);
+    assert_eq!(cache.status(&2), EntryStatus::Present);
+    assert_eq!(cache.take(&2), Some(2));
+    assert_eq!(cache.take(&3), Some(3));
+    assert_eq!(cache.status(&1), EntryStatus::Absent);
+    assert_eq!(cache.status(&2), EntryStatus::RecentlyRemoved);
+    assert_eq!(cache.status(&3), EntryStatus::RecentlyRemoved);
+
+    cache.put(3, 3);
+    assert_eq!(cache.status(&3), EntryStatus::Present);
+    cache.remove(&3);
+    assert_eq!(cache.status(&3), EntryStatus::Absent);
+    assert_eq!(cache.status(&2), EntryStatus::RecentlyRemoved);
+
+    // a re-inserted key that is evicted later is not reported as taken either
+    cache.put(2, 2);
+    for i in 10..20 {
+        cache.put(i, i);
+    }
+    assert_eq!(cache.status(&2), EntryStatus::Absent);
+
+    cache.take(&15);
+    cache.set_tombstones(0);
+    assert_eq!(cache.status(&15), EntryStatus::Absent);
+    assert_eq!(cache.len(), 9);
+}
+
+#[test]
+fn iter_eviction_order() {
+    let mut cache = FbrCache::new(20);
+    for i in 0..30 {
+        cache.put(i, i);
+        for _ in 0..i % 4 {
+            cache.get(&i);
+        }
+    }
+    cache.pin(&25);
+    let order = cache
+        .iter_eviction_order()
+        .map(|(k, v, count)| {
+            assert_eq!(k, v);
+            assert_eq!(cache.count_of(k), Some(count));
+            *k
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(order.len(), 20);
+    assert_eq!(order.last(), Some(&25));
+    #[cfg(debug_assertions)]
+    assert_eq!(cache.explain_eviction().map(|e| e.key), Some(order[0]));
+
+    let demoted = cache.demote(19);
+    let demoted = demoted.into_iter().map(|(k, _, _)| k).collect::<Vec<_>>();
+    assert_eq!(demoted, order[..19]);
+    assert_eq!(cache.iter_eviction_order().count(), 1);
+}
+
+#[test]
+fn with_config() {
+    let config = FbrConfig {
+        capacity: 10,
+        age_threshold: 4,
+        max_count: 5,
+        evict_policy: EvictPolicy::Lru,
+        scan_limit: 3,
+        promotion_policy: PromotionPolicy::default(),
+        overwrite_on_put: true,
+        hard_capacity: 12,
+        grow_on_demand: Some(30),
+        auto_age_threshold: Some(20),
+        freq_decay_on_miss: 2,
+        region_aging: [0, 1, 2],
+        tombstones: 3,
+    };
+    let cache = FbrCache::<u32, u32, 3>::with_config(config).unwrap();
+    assert_eq!(cache.capacity(), 10);
+    assert_eq!(cache.effective_age_threshold(), 40);
+    assert_eq!(cache.max_count(), 5);
+    assert_eq!(cache.evict_policy(), EvictPolicy::Lru);
+    assert_eq!(cache.scan_limit(), 3);
+    assert!(cache.overwrite_on_put());
+    assert_eq!(cache.hard_capacity(), 12);
+    assert_eq!(cache.freq_decay_on_miss(), 2);
+    assert_eq!(cache.region_aging(Region::Old), 2);
+    let state = cache.export_state();
+    assert_eq!(state.grow_on_demand, Some(30));
+    assert_eq!(state.auto_age_threshold, Some(20));
+    assert_eq!(state.tombstones, 3);
+
+    let mut cache = FbrCache::<u32, u32, 8>::with_config(FbrConfig {
+        capacity: 20,
+        ..FbrConfig::default()
+    })
+    .unwrap();
+    let reference = FbrCache::<u32, u32, 8>::new(20);
+    assert_eq!(cache.effective_age_threshold(), 2000);
+    assert_eq!(cache.max_count(), reference.max_count());
+    assert_eq!(cache.evict_policy(), reference.evict_policy());
+    assert_eq!(cache.scan_limit(), reference.scan_limit());
+    for i in 0..30 {
+        cache.put(i, i);
+    }
+    assert_eq!(cache.len(), 20);
+
+    let err = FbrCache::<u32, u32, 8>::with_config(FbrConfig::default()).unwrap_err();
+    assert_eq!(err, ConfigError::CapacityTooSmall(0));
+    let err = FbrCache::<u32, u32, 8>::with_config(FbrConfig {
+        capacity: 3,
+        ..FbrConfig::default()
+    })
+    .unwrap_err();
+    assert_eq!(err, ConfigError::CapacityTooSmall(3));
+    let err = FbrCache::<u32, u32, 8>::with_config(FbrConfig {
+        capacity: 4,
+        age_threshold: 0,
+        ..FbrConfig::default()
+    })
+    .unwrap_err();
+    assert_eq!(err, ConfigError::ZeroAgeThreshold);
+    let err = FbrCache::<u32, u32, 8>::with_config(FbrConfig {
+        capacity: 4,
+        max_count: 0,
+        ..FbrConfig::default()
+    })
+    .unwrap_err();
+    assert_eq!(err, ConfigError::ZeroMaxCount);
+}
+
+#[test]
+fn merge_with() {
+    let mut mine = FbrCache::new(10);
+    let mut theirs = FbrCache::new(10);
+    for i in 0..4 {
+        mine.put(i, 1);
+        theirs.put(i + 2, 10);
+    }
+    mine.get(&2);
+    mine.pin(&3);
+    theirs.get(&3);
+    theirs.get(&3);
+    theirs.put(9, 100);
+    let sums = [2, 3].map(|k| mine.count_of(&k).unwrap() + theirs.count_of(&k).unwrap());
+    assert_ne!(sums, [0, 0]);
+    let seen = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let log = seen.clone();
+    mine.on_insert(move |k, _, _| log.lock().unwrap().push(*k));
+
+    let mut conflicts = vec![];
+    mine.merge_with(theirs, |key, a, b| {
+        conflicts.push(*key);
+        a + b
+    });
+    assert_eq!(conflicts, vec![2, 3]);
+    assert_eq!(mine.len(), 7);
+    assert_eq!(mine.peek(&2), Some(&11));
+    assert_eq!(mine.peek(&3), Some(&11));
+    assert_eq!(mine.peek(&5), Some(&10));
+    assert_eq!(mine.count_of(&2), Some(sums[0]));
+    assert_eq!(mine.count_of(&3), Some(sums[1]));
+    assert!(mine.is_pinned(&3));
+    assert_eq!(mine.lru_order()[0], 9);
+    // shared keys are replaced in place, not inserted
+    assert_eq!(*seen.lock().unwrap(), vec![4, 5, 9]);
+    assert_eq!(mine.inserts(), 7);
+    mine.check_invariants().unwrap();
+}
+
+#[test]
+fn estimated_lifetime() {
+    let mut cache = FbrCache::new(100);
+    assert_eq!(cache.estimated_lifetime(), f64::INFINITY);
+    for i in 0..100 {
+        cache.put(i, i);
+    }
+    assert_eq!(cache.inserts(), 100);
+    assert_eq!(cache.estimated_lifetime(), f64::INFINITY);
+    for i in 100..10_000 {
+        cache.put(i, i);
+    }
+    let lifetime = cache.estimated_lifetime();
+    assert!((100.0..102.0).contains(&lifetime), "{lifetime}");
+    cache.reset_stats();
+    for i in 10_000..11_000 {
+        cache.put(i, i);
+    }
+    assert_eq!(cache.estimated_lifetime(), 100.0);
+
+    // hot items repeatedly promoted from behind push cold items out faster
+    cache.reset_stats();
+    for i in 12_000..14_000 {
+        cache.put(i, i);
+        cache.get(&(10_980 + i % 20));
+    }
+    assert!(cache.promotions() > 0);
+    let lifetime = cache.estimated_lifetime();
+    assert!(lifetime < 90.0, "{lifetime}");
+}
+
+#[test]
+fn prepopulate() {
+    let mut cache = FbrCache::<u32, String, 8>::new(10);
+    cache.put(100, s("existing"));
+    let mut calls = vec![];
+    let keys = [1, 2, 2, 100, 3, 1].into_iter().chain(4..50);
+    cache.prepopulate(keys, |k| {
+        calls.push(*k);
+        k.to_string()
+    });
+    assert_eq!(calls, (1..10).collect::<Vec<_>>());
+    assert_eq!(cache.len(), 10);
+    assert!(cache.has_been_full());
+    assert_eq!(cache.inserts(), 10);
+    assert_eq!(cache.lru_order(), vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 100]);
+    assert_eq!(cache.peek(&4), Some(&s("4")));
+    assert_eq!(cache.region_of(&1), Some(Region::New));
+    assert_eq!(cache.region_of(&100), Some(Region::Old));
+    cache.check_invariants().unwrap();
+
+    cache.prepopulate(200..300, |_| unreachable!());
+    cache.put(10, s("10"));
+    assert_eq!(cache.len(), 10);
+    cache.check_invariants().unwrap();
+}
+
+#[test]
+fn into_lru_order() {
+    let mut cache = FbrCache::<u32, String, 3>::with_age_threshold(10, 4);
+    for n in 0..40 {
+        cache.put(n % 13, n.to_string());
+        cache.get(&(n % 4));
+    }
+    cache.pin(&2);
+    let expected = cache
+        .lru_order()
+        .into_iter()
+        .map(|k| (k, cache.peek(&k).unwrap().clone()))
+        .collect::<Vec<_>>();
+    assert_eq!(expected.len(), 10);
+    assert_eq!(cache.into_lru_order(), expected);
+}
+
+#[test]
+fn mutation_sequence_keeps_invariants() {
+    // every mutation path checks the invariants itself in test builds
+    let mut cache = FbrCache::<u32, u32, 4>::with_age_threshold(16, 2);
+    cache.set_max_count(6);
+    cache.set_grow_on_demand(32);
+    cache.set_tombstones(4);
+    let mut x = 1u32;
+    for round in 0..2_000u32 {
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        let key = x % 48;
+        match round % 11 {
+            0 => {
+                cache.put_prio(key, round);
+            }
+            1 => {
+                cache.take(&key);
+            }
+            2 => {
+                cache.pin(&key);
+            }
+            3 => {
+                cache.unpin(&key);
+            }
+            4 => {
+                cache.demote(2);
+            }
+            5 => {
+                cache.replace_with(&key, |v| v + 1);
+            }
+            6 => {
+                cache.touch_many(&[key, key + 1, key + 2]);
+            }
+            7 => {
+                cache.prune_to(8);
+            }
+            8 => {
+                cache.prepopulate(key..key + 4, |k| *k);
+            }
+            _ => {
+                cache.put(key, round);
+                cache.get(&(key / 2));
+            }
+        }
+        if round % 500 == 499 {
+            let mut other = FbrCache::with_age_threshold(16, 2);
+            other.put(key, 0);
+            other.put(key + 100, 0);
+            cache.merge_with(other, |_, a, b| a.max(b));
+            cache.set_capacity(16);
+        }
+    }
+    cache.check_invariants().unwrap();
+}
+
+#[test]
+fn estimated_bytes() {
+    let mut cache = FbrCache::<u64, [u8; 100], 8>::new(10);
+    assert_eq!(cache.estimated_bytes(|_, _| unreachable!()), 0);
+    for i in 0..15 {
+        cache.put(i, [0; 100]);
+    }
+    let overhead = FbrCache::<u64, [u8; 100], 8>::entry_overhead_bytes();
+    // at least two links of two pointers each and the count, plus key and pointer in the index
+    assert!(overhead >= 4 * std::mem::size_of::<usize>() + std::mem::size_of::<usize>() + 16);
+    let sizer = |_: &u64, v: &[u8; 100]| 8 + v.len();
+    assert_eq!(cache.estimated_bytes(sizer), 10 * (108 + overhead));
+
+    let mut cache = FbrCache::<u32, String, 8>::new(10);
+    cache.put(1, s("hello"));
+    cache.put(2, s("world!"));
+    let inline = std::mem::size_of::<u32>() + std::mem::size_of::<String>();
+    let overhead = FbrCache::<u32, String, 8>::entry_overhead_bytes();
+    assert_eq!(
+        cache.estimated_bytes(|_, v| inline + v.capacity()),
+        2 * (inline + overhead) + 11
+    );
+}
+
+#[test]
+fn retain_mut() {
+    let mut cache = FbrCache::new(10);
+    for i in 0..10 {
+        cache.put(i, i % 3);
+    }
+    cache.get(&4);
+    let counts = (0..10).map(|i| cache.count_of(&i)).collect::<Vec<_>>();
+    let order = cache.lru_order();
+
+    let mut seen = vec![];
+    cache.retain_mut(|k, ttl| {
+        seen.push(*k);
+        if *ttl == 0 {
+            return false;
+        }
+        *ttl -= 1;
+        true
+    });
+    assert_eq!(seen, order);
+    assert_eq!(cache.len(), 6);
+    for i in 0..10 {
+        match i % 3 {
+            0 => assert_eq!(cache.peek(&i), None),
+            ttl => {
+                assert_eq!(cache.peek(&i), Some(&(ttl - 1)));
+                assert_eq!(cache.count_of(&i), counts[i as usize]);
+            }
+        }
+    }
+    let expected = order.into_iter().filter(|k| k % 3 != 0).collect::<Vec<_>>();
+    assert_eq!(cache.lru_order(), expected);
+    cache.check_invariants().unwrap();
+}
+
+#[test]
+fn recommended_c_max() {
+    use crate::recommended_c_max;
+    assert_eq!(recommended_c_max(0), 8);
+    assert_eq!(recommended_c_max(4), 12);
+    assert_eq!(recommended_c_max(1000), 40);
+    assert_eq!(recommended_c_max(1 << 20), 84);
+    assert_eq!(recommended_c_max(usize::MAX), 4 * usize::BITS as usize);
+    let mut previous = 0;
+    for shift in 0..usize::BITS {
+        for capacity in [(1usize << shift) - 1, 1 << shift, (1 << shift) + 1] {
+            let c_max = recommended_c_max(capacity);
+            assert!((8..=256).contains(&c_max));
+            assert!(c_max >= previous);
+            previous = c_max;
+        }
+    }
+    const C_MAX: usize = recommended_c_max(100);
+    let mut cache = FbrCache::<u32, u32, C_MAX>::with_age_threshold(100, 10);
+    cache.put(1, 1);
+    assert_eq!(cache.get(&1), Some(&1));
+}
+
+#[test]
+fn for_each() {
+    let mut cache = FbrCache::<u32, String, 3>::with_age_threshold(10, 4);
+    for n in 0..30 {
+        cache.put(n % 13, n.to_string());
+        cache.get(&(n % 4));
+    }
+    let mut visited = vec![];
+    let mut histogram = [0; 3];
+    cache.for_each(|k, v, count, region| {
+        visited.push((*k, v.clone(), count, region));
+        histogram[region as usize] += 1;
+    });
+    let expected = cache
+        .iter()
+        .map(|(k, v, c, r)| (*k, v.clone(), c, r))
+        .collect::<Vec<_>>();
+    assert_eq!(visited, expected);
+    assert_eq!(histogram, [3, 4, 3]);
+}
+
+#[test]
+fn split_at_rank() {
+    let mut cache = FbrCache::<u32, String, 3>::with_age_threshold(10, 4);
+    for n in 0..30 {
+        cache.put(n % 13, n.to_string());
+        cache.get(&(n % 4));
+    }
+    let original = cache
+        .iter()
+        .map(|(k, v, c, _)| (*k, v.clone(), c))
+        .collect::<Vec<_>>();
+    assert_eq!(original.len(), 10);
+
+    let split = cache.split_at_rank(3);
+    let items = |cache: &FbrCache<u32, String, 3>| {
+        cache
+            .iter()
+            .map(|(k, v, c, _)| (*k, v.clone(), c))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(items(&cache), original[..3]);
+    assert_eq!(items(&split), original[3..]);
+    assert_eq!(cache.capacity(), 10);
+    assert_eq!(split.capacity(), 7);
+    assert_eq!(split.region_of(&split.lru_order()[6]), Some(Region::Old));
+    cache.check_invariants().unwrap();
+    split.check_invariants().unwrap();
+
+    assert_eq!(cache.split_at_rank(5).len(), 0);
+    assert_eq!(cache.len(), 3);
+    let all = cache.split_at_rank(0);
+    assert!(cache.is_empty());
+    assert_eq!(items(&all), original[..3]);
+    assert_eq!(all.capacity(), 4);
+}
+
+#[test]
+fn touch_prio() {
+    let mut cache = FbrCache::new(10);
+    for i in 0..10 {
+        cache.put(i, i);
+    }
+    // 0 and 1 are in the old region, so a plain hit increments their counts
+    assert_eq!(cache.region_of(&0), Some(Region::Old));
+    assert_eq!(cache.region_of(&1), Some(Region::Old));
+    cache.get(&0);
+    assert!(cache.touch_prio(&1));
+    assert_eq!(cache.count_of(&0), Some(1));
+    assert_eq!(cache.count_of(&1), Some(2));
+    assert_eq!(cache.lru_order()[0], 1);
+
+    // within the new region only the extra increment applies
+    assert!(cache.touch_prio(&1));
+    assert_eq!(cache.count_of(&1), Some(3));
+    cache.get(&0);
+    assert_eq!(cache.count_of(&0), Some(1));
+
+    assert!(!cache.touch_prio(&42));
+    assert_eq!((cache.hits(), cache.misses()), (4, 1));
+    cache.set_max_count(3);
+    cache.touch_prio(&1);
+    assert_eq!(cache.count_of(&1), Some(3));
+    cache.check_invariants().unwrap();
+}
+
+#[test]
+fn import_inconsistent_state() {
+    let state = crate::CacheState {
+        capacity: 5,
+        age_threshold: 20,
+        max_count: 4,
+        evict_policy: EvictPolicy::default(),
+        scan_limit: usize::MAX,
+        promotion_policy: PromotionPolicy::default(),
+        overwrite_on_put: false,
+        slack: 0,
+        grow_on_demand: None,
+        auto_age_threshold: None,
+        freq_decay_on_miss: 0,
+        region_aging: [1; 3],
+        tombstones: 0,
+        entries: vec![
+            (1, s("a"), 9),
+            (2, s("b"), 0),
+            (1, s("stale"), 1),
+            (3, s("c"), 2),
+            (2, s("stale"), 3),
+            (4, s("d"), 0),
+            (5, s("e"), 1),
+            (6, s("f"), 1),
+        ],
+    };
+    let cache = FbrCache::<u32, String, 3>::import_state(state);
+    cache.check_invariants().unwrap();
+    assert_eq!(cache.lru_order(), vec![1, 2, 3, 4, 5]);
+    assert_eq!(cache.peek(&1), Some(&s("a")));
+    assert_eq!(cache.peek(&2), Some(&s("b")));
+    assert_eq!(cache.count_of(&1), Some(4));
+    assert_eq!(cache.frequency_sum(Region::New), 4);
+    assert_eq!(cache.region_of(&5), Some(Region::Old));
+}
+
+#[test]
+fn peek_or_insert_with() {
+    let mut cache = FbrCache::new(10);
+    for i in 0..10 {
+        cache.put(i, i.to_string());
+    }
+    cache.get(&0);
+    let order = cache.lru_order();
+    let counts = cache.iter().map(|(.., c, _)| c).collect::<Vec<_>>();
+
+    for _ in 0..3 {
+        assert_eq!(cache.peek_or_insert_with(5, || unreachable!()), "5");
+        assert_eq!(cache.peek_or_insert_with(1, || unreachable!()), "1");
+    }
+    assert_eq!(cache.lru_order(), order);
+    assert_eq!(cache.iter().map(|(.., c, _)| c).collect::<Vec<_>>(), counts);
+    assert_eq!((cache.hits(), cache.misses()), (1, 0));
+
+    assert_eq!(cache.peek_or_insert_with(42, || s("new")), "new");
+    assert_eq!(cache.len(), 10);
+    assert_eq!(cache.lru_order()[0], 42);
+    assert_eq!(cache.evictions(), 1);
+    assert_eq!((cache.hits(), cache.misses()), (1, 0));
+    assert_eq!(cache.peek_or_insert_with(42, || unreachable!()), "new");
+}
+
+#[test]
+fn set_capacity_with_pinned_items() {
+    let mut cache = FbrCache::new(8);
+    for i in 0..8 {
+        cache.put(i, i);
+    }
+    for i in 2..8 {
+        cache.pin(&i);
+    }
+    cache.set_capacity(4);
+    assert_eq!(cache.capacity(), 6);
+    assert_eq!(cache.len(), 6);
+    assert!(!cache.contains_key(&0) && !cache.contains_key(&1));
+
+    for i in 2..8 {
+        cache.unpin(&i);
+    }
+    cache.set_capacity(4);
+    assert_eq!(cache.capacity(), 4);
+    assert_eq!(cache.len(), 4);
+    cache.check_invariants().unwrap();
+}
+
+#[test]
+fn not_in_new_keeps_victim_order() {
+    let mut cache = FbrCache::new(10);
+    cache.set_promotion_policy(PromotionPolicy::NotInNew);
+    for i in 0..10 {
+        cache.put(i, i);
+    }
+    cache.get(&9);
+    cache.get(&8);
+    cache.get(&7);
+    assert_eq!(&cache.lru_order()[..3], &[9, 8, 7]);
+    cache.check_invariants().unwrap();
+
+    let mut evicted = vec![];
+    for i in 10..20 {
+        cache.put(i, i);
+        for k in [7, 8, 9] {
+            if !evicted.contains(&k) && !cache.contains_key(&k) {
+                evicted.push(k);
+            }
+        }
+    }
+    assert_eq!(evicted, vec![7, 8, 9]);
+}
+
+#[test]
+fn rebuild_with_capacity_keeps_settings() {
+    let mut cache = FbrCache::<u32, u32, 8>::with_absolute_age_threshold(10, 5);
+    cache.set_max_count(3);
+    cache.set_evict_policy(EvictPolicy::Lru);
+    cache.set_scan_limit(2);
+    cache.set_promotion_policy(PromotionPolicy::NotInNew);
+    cache.set_overwrite_on_put(true);
+    cache.set_hard_capacity(12);
+    cache.put(1, 1);
+
+    let cache = cache.rebuild_with_capacity(10);
+    assert_eq!(cache.effective_age_threshold(), 5);
+    assert_eq!(cache.max_count(), 3);
+    assert_eq!(cache.evict_policy(), EvictPolicy::Lru);
+    assert_eq!(cache.scan_limit(), 2);
+    assert_eq!(cache.promotion_policy(), PromotionPolicy::NotInNew);
+    assert!(cache.overwrite_on_put());
+    assert_eq!(cache.hard_capacity(), 12);
+
+    let cache = cache.rebuild_with_capacity(30);
+    assert_eq!(cache.effective_age_threshold(), 15);
+    assert_eq!(cache.hard_capacity(), 32);
+}
+
+#[test]
+fn clear_except_top_skips_pinned() {
+    let mut cache = FbrCache::new(10);
+    for i in 0..10 {
+        cache.put(i, i);
+    }
+    assert!(cache.pin(&0) && cache.pin(&1));
+    cache.put_prio_n(20, 20, 5);
+    let evictions = cache.evictions();
+    assert_eq!(cache.clear_except_top(1), 7);
+    assert_eq!(cache.evictions(), evictions + 7);
+    assert_eq!(cache.len(), 3);
+    assert!(cache.contains_all(&[0, 1, 20]));
+    assert_eq!(cache.total_count, 5);
+    cache.check_invariants().unwrap();
+}
+
+#[test]
+fn initial_count_contributes_to_aging() {
+    let mut cache = FbrCache::<u32, u32, 8>::with_age_threshold(4, 1);
+    cache.put_prio_n(0, 0, 7);
+    assert_eq!(cache.total_count, 7);
+    for i in 1..4 {
+        cache.put(i, i);
+    }
+    for _ in 0..10 {
+        for i in 0..4 {
+            cache.get(&i);
+        }
+        // no evictions, so the sum is exact
+        assert_eq!(cache.total_count, cache.iter().map(|(.., c, _)| c).sum());
+        assert!(cache.total_count <= 4);
+    }
+    assert!(cache.age_events() < 40);
+
+    let mut cache = FbrCache::<u32, u32, 8>::with_age_threshold(4, 1);
+    cache.put_prio(0, 0);
+    cache.entry(1).or_insert_prio(1);
+    assert_eq!(cache.total_count, 2);
+}
+
+#[test]
+fn set_max_count_keeps_chain_order() {
+    let mut cache = FbrCache::<u32, u32, 8>::new(8);
+    cache.put_prio_n(0, 0, 3);
+    cache.put_prio_n(1, 1, 1);
+    for k in 2..8 {
+        cache.put_prio_n(k, k, 5);
+    }
+    cache.set_max_count(1);
+    cache.check_invariants().unwrap();
+    cache.put(100, 100);
+    // both now count 1, so the least recent one goes
+    assert!(!cache.contains_key(&0));
+    assert!(cache.contains_key(&1));
+    cache.check_invariants().unwrap();
+}
+
+#[test]
+fn touch_prio_not_in_new_keeps_chain_order() {
+    let mut cache = FbrCache::<u32, u32, 8>::new(10);
+    cache.set_promotion_policy(PromotionPolicy::NotInNew);
+    cache.put_prio_n(99, 99, 3);
+    for i in 0..9 {
+        cache.put(i, i);
+    }
+    assert!(cache.touch_prio(&7));
+    assert!(cache.touch_prio(&6));
+    assert_eq!(cache.lru_order()[..2], [6, 7]);
+    cache.check_invariants().unwrap();
+    cache.put(100, 100);
+    cache.check_invariants().unwrap();
+    assert!(!cache.contains_key(&0));
+    assert!(cache.contains_all(&[99, 6, 7]));
+    for i in 1..5 {
+        cache.remove(&i);
+    }
+    for i in 101..107 {
+        cache.put(i, i);
+    }
+    cache.check_invariants().unwrap();
+    assert!(cache.contains_key(&99));
+}
+
+#[test]
+fn derived_caches_keep_settings() {
+    fn assert_settings<V>(cache: &FbrCache<u32, V, 8>) {
+        assert_eq!(cache.region_aging(Region::New), 0);
+        assert_eq!(cache.region_aging(Region::Old), 2);
+        assert_eq!(cache.freq_decay_on_miss(), 3);
+        assert_eq!(cache.auto_age.as_ref().map(|auto| auto.interval), Some(50));
+        assert_eq!(cache.tombstone_limit, 4);
+        assert_eq!(cache.growth.as_ref().map(|g| g.max_capacity), Some(100));
+        assert_eq!(cache.scan_limit(), 2);
+        assert_eq!(cache.hard_capacity(), cache.capacity() + 2);
+        assert_eq!(cache.max_count(), 6);
+        assert_eq!(cache.evict_policy(), EvictPolicy::Lru);
+        assert_eq!(cache.promotion_policy(), PromotionPolicy::NotInNew);
+        assert!(cache.overwrite_on_put());
+        cache.check_invariants().unwrap();
+    }
+    let mut cache = FbrCache::<u32, u32, 8>::with_age_threshold(20, 1000);
+    cache.set_region_aging(Region::New, 0);
+    cache.set_region_aging(Region::Old, 2);
+    cache.set_freq_decay_on_miss(3);
+    cache.set_auto_age_threshold(Some(50));
+    cache.set_tombstones(4);
+    cache.set_grow_on_demand(100);
+    cache.set_scan_limit(2);
+    cache.set_hard_capacity(22);
+    cache.set_max_count(6);
+    cache.set_evict_policy(EvictPolicy::Lru);
+    cache.set_promotion_policy(PromotionPolicy::NotInNew);
+    cache.set_overwrite_on_put(true);
+    for i in 0..20 {
+        cache.put(i, i);
+    }
+    assert_settings(&cache);
+
+    assert_settings(&cache.map_values(|v| v.to_string()));
+    assert_settings(&cache.split_off_region(Region::Old));
+    assert_settings(&cache.split_at_rank(5));
+    assert_settings(&cache.rebuild_with_capacity(10));
 }